@@ -0,0 +1,29 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use molang::compile;
+
+/// A script of roughly `statements * 60` bytes mixing every kind of token.
+fn script(statements: usize) -> String {
+    (0..statements)
+        .map(|i| format!("v.x{i} = math.max(q.a[{i}], (v.b ?? 2) * 3) == \"str\" ? 1 : 0;\n"))
+        .collect()
+}
+
+fn tokenise(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+
+    // Time per byte should stay flat as the script grows.
+    for statements in [16, 64, 256, 1024] {
+        let source = script(statements);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(source.len()),
+            &source,
+            |b, source| b.iter(|| compile(black_box(source)).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, tokenise);
+criterion_main!(benches);
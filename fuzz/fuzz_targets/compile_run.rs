@@ -0,0 +1,35 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use molang::{compile, format_source, minify, Limits, MinifyError, Runtime, Value};
+
+// Compiling and running must return errors, never panic.
+fuzz_target!(|source: &str| {
+    let mut runtime = Runtime::builder()
+        .variable("v", Value::Struct(HashMap::new()))
+        .function("max", |args| Ok(args.into_iter().next().unwrap_or(Value::Null)))
+        .query_provider("q", |_: &str, args: Vec<Value>| {
+            Ok(args.into_iter().next().unwrap_or(Value::Number(1.0)))
+        })
+        .limits(Limits {
+            max_steps: Some(10_000),
+            max_string_length: Some(1024),
+            ..Default::default()
+        })
+        .optimise(true)
+        .build();
+
+    let _ = runtime.eval_str(source);
+
+    // Formatting is stable and never changes what a script means.
+    if let Ok(formatted) = format_source(source) {
+        assert_eq!(Ok(&formatted), format_source(&formatted).as_ref());
+        assert_eq!(compile(source).ok(), compile(&formatted).ok());
+    }
+
+    for fold in [false, true] {
+        assert!(!matches!(minify(source, fold), Err(MinifyError::Mismatch(_))));
+    }
+});
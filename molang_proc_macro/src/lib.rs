@@ -31,7 +31,7 @@ fn molang_struct(input: TokenStream) -> TokenStream {
         impl molang::ToMolangValue for #name {
             fn to_value(self) -> molang::Value {
                 let mut fields = std::collections::HashMap::new();
-                #(fields.insert(stringify!(#field_idents).to_string(), self.#field_idents.to_value());)*
+                #(fields.insert(stringify!(#field_idents).to_string(), molang::ToMolangValue::to_value(self.#field_idents));)*
                 molang::Value::Struct(fields)
             }
         }
@@ -42,7 +42,7 @@ fn molang_struct(input: TokenStream) -> TokenStream {
                     molang::Value::Struct(mut st) => {
                         Ok(#name { #( #field_idents : 
                             match st.remove(&stringify!(#field_idents).to_string()) {
-                                Some(x) => #field_types::from_value(x)?,
+                                Some(x) => <#field_types as molang::FromMolangValue>::from_value(x)?,
                                 None => return Err(molang::MolangError::TypeError(stringify!(#field_types).to_string(), "None".to_string()))
                             },
                         )* })
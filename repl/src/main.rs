@@ -5,7 +5,7 @@ use std::{
     rc::Rc,
 };
 
-use molang::{External, Function, MolangEq, MolangError, Value};
+use molang::{format_source, External, MolangEq, MolangError, Number, Runtime, Value};
 
 #[derive(Debug)]
 struct Vector {
@@ -24,7 +24,7 @@ impl MolangEq for Vector {
 impl External for Vector {
     fn get(&mut self, property: &str) -> Value {
         match property {
-            "length" => Value::Number(self.vec.len() as f32),
+            "length" => Value::Number(self.vec.len() as Number),
             _ => Value::Null,
         }
     }
@@ -43,12 +43,18 @@ impl External for Vector {
     }
 
     fn set(&mut self, property: &str, value: Value) -> Result<(), MolangError> {
-        Err(MolangError::NotAssignable(format!("vec.{property}")))
+        match (property, value) {
+            ("length", Value::Number(n)) if can_convert_to_usize(n) => {
+                self.vec.resize(n as usize, Value::Null);
+                Ok(())
+            }
+            _ => Err(MolangError::NotAssignable(format!("vec.{property}"))),
+        }
     }
 
     fn index_get(&mut self, index: Value) -> Result<Value, MolangError> {
         let index = match index {
-            Value::Number(n) if can_convert_f32_to_usize(n) => n as usize,
+            Value::Number(n) if can_convert_to_usize(n) => n as usize,
             n => {
                 return Err(MolangError::BadAccess(
                     format!("Index {n:?}"),
@@ -59,9 +65,9 @@ impl External for Vector {
         Ok(self.vec.get(index).unwrap_or(&Value::Null).clone())
     }
 
-    fn index_set<'a>(&'a mut self, index: Value, value: Value) -> Result<(), MolangError> {
+    fn index_set(&mut self, index: Value, value: Value) -> Result<(), MolangError> {
         let index = match index {
-            Value::Number(n) if can_convert_f32_to_usize(n) => n as usize,
+            Value::Number(n) if can_convert_to_usize(n) => n as usize,
             n => {
                 return Err(MolangError::BadAccess(
                     format!("Index {n:?}"),
@@ -70,7 +76,7 @@ impl External for Vector {
             }
         };
 
-        if self.vec.len() > index {
+        if index >= self.vec.len() {
             Err(MolangError::BadAccess(index.to_string(), "Vec".to_string()))
         } else {
             self.vec[index] = value;
@@ -80,25 +86,74 @@ impl External for Vector {
     }
 }
 
-fn can_convert_f32_to_usize(x: f32) -> bool {
-    x >= 0.0 && x.is_finite() && x.fract() == 0.0 && x <= usize::MAX as f32
+fn can_convert_to_usize(x: Number) -> bool {
+    x >= 0.0 && x.is_finite() && x.fract() == 0.0 && x <= usize::MAX as Number
+}
+
+/// `fmt [--write] [FILE...]` prints each file formatted, or rewrites it with
+/// `--write`. Without files it formats standard input.
+fn fmt(args: &[String]) -> i32 {
+    let write = args.iter().any(|a| a == "--write");
+    let files: Vec<&String> = args.iter().filter(|a| *a != "--write").collect();
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut source) {
+            eprintln!("{error}");
+            return 1;
+        }
+        return match format_source(&source) {
+            Ok(formatted) => {
+                println!("{formatted}");
+                0
+            }
+            Err(error) => {
+                eprintln!("{error}");
+                1
+            }
+        };
+    }
+
+    let mut status = 0;
+
+    for file in files {
+        let formatted = std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|source| format_source(&source).map_err(|e| e.to_string()));
+
+        let result = match formatted {
+            Ok(formatted) if write => {
+                std::fs::write(file, formatted + "\n").map_err(|e| e.to_string())
+            }
+            Ok(formatted) => {
+                println!("{formatted}");
+                Ok(())
+            }
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            eprintln!("{file}: {error}");
+            status = 1;
+        }
+    }
+
+    status
 }
 
 fn main() {
-    let mut constants = HashMap::new();
-    let mut variables = HashMap::new();
-    variables.insert("variable".to_string(), Value::Struct(HashMap::new()));
-    let mut aliases = HashMap::new();
-    aliases.insert("v".to_string(), "variable".to_string());
-
-    constants.insert(
-        "array".to_string(),
-        Value::Function(Function {
-            f: Rc::new(RefCell::new(|args| {
-                Ok(Value::External(Rc::new(RefCell::new(Vector { vec: args }))))
-            })),
-        }),
-    );
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "fmt") {
+        std::process::exit(fmt(&args[1..]));
+    }
+
+    let mut runtime = Runtime::builder()
+        .variable("variable", Value::Struct(HashMap::new()))
+        .alias("v", "variable")
+        .function("array", |args| {
+            Ok(Value::External(Rc::new(RefCell::new(Vector { vec: args }))))
+        })
+        .build();
 
     println!("fmccl/molang REPL: ");
 
@@ -112,14 +167,24 @@ fn main() {
         let mut line = "".into();
         let len = std::io::stdin().lock().read_line(&mut line).unwrap();
 
-        let compiled = molang::compile(&line[..len]);
+        if len == 0 {
+            break;
+        }
+
+        // `:print <expr>` shows the expression as it was understood.
+        let (print, source) = match line.strip_prefix(":print") {
+            Some(source) => (true, source),
+            None => (false, line.as_str()),
+        };
+
+        let compiled = runtime.compile(source);
 
         match compiled {
+            Ok(compiled) if print => {
+                println!("{compiled}");
+            }
             Ok(compiled) => {
-                println!(
-                    "{:?}",
-                    molang::run(&compiled, &constants, &mut variables, &aliases)
-                );
+                println!("{:?}", runtime.eval(&compiled));
             }
             Err(error) => {
                 println!("{error:?}");
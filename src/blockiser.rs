@@ -1,12 +1,28 @@
-use crate::{parser::treeify, tokeniser::Token, CompileError, Expr};
+use crate::{
+    parser::{treeify, ExprSpan},
+    tokeniser::{Spanned, Token},
+    CompileError, Expr,
+};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     pub multiple: bool,
     pub statements: Vec<Expr>,
 }
 
-pub fn blockise(tokens: Vec<Token>) -> Result<Block, CompileError> {
+/// Splits `tokens` into statements. No tokens at all, as in `{}`, is an empty block.
+pub fn blockise(
+    tokens: Vec<Spanned<Token>>,
+    depth: usize,
+    mut spans: Option<&mut Vec<ExprSpan>>,
+) -> Result<Block, CompileError> {
+    if tokens.is_empty() {
+        return Ok(Block {
+            multiple: true,
+            statements: Vec::new(),
+        });
+    }
+
     let mut statements = Vec::new();
 
     let mut current_start: usize = 0;
@@ -14,20 +30,21 @@ pub fn blockise(tokens: Vec<Token>) -> Result<Block, CompileError> {
     let mut multiple = false;
 
     for (index, token) in tokens.iter().enumerate() {
-        if *token == Token::Semicolon {
+        if token.value == Token::Semicolon {
             multiple = true;
 
-            statements.push(treeify(&tokens[current_start..index])?);
+            statements.push(treeify(
+                &tokens[current_start..index],
+                depth,
+                spans.as_deref_mut(),
+            )?);
             current_start = index + 1;
         }
     }
 
+    // Without a `;`, this is the whole of `tokens`.
     if !&tokens[current_start..].is_empty() {
-        statements.push(treeify(&tokens[current_start..])?);
-    }
-
-    if !multiple {
-        statements = vec![treeify(&tokens)?];
+        statements.push(treeify(&tokens[current_start..], depth, spans)?);
     }
 
     Ok(Block {
@@ -36,10 +53,12 @@ pub fn blockise(tokens: Vec<Token>) -> Result<Block, CompileError> {
     })
 }
 
+#[cfg(test)]
 mod test {
     use crate::{
         blockiser::{blockise, Block},
-        parser::Instruction,
+        limits::DEFAULT_MAX_DEPTH,
+        parser::{ExprSpan, Instruction},
         tokeniser::tokenise,
         Expr, Value,
     };
@@ -54,7 +73,12 @@ mod test {
                     Expr::Literal(Value::Number(1.0))
                 ]
             },
-            blockise(tokenise("1; 1;").unwrap()).unwrap()
+            blockise(
+                tokenise("1; 1;", None, DEFAULT_MAX_DEPTH).unwrap(),
+                DEFAULT_MAX_DEPTH,
+                None
+            )
+            .unwrap()
         )
     }
 
@@ -70,7 +94,42 @@ mod test {
                     )))))
                 ]
             },
-            blockise(tokenise("1; return 1;").unwrap()).unwrap()
+            blockise(
+                tokenise("1; return 1;", None, DEFAULT_MAX_DEPTH).unwrap(),
+                DEFAULT_MAX_DEPTH,
+                None
+            )
+            .unwrap()
         )
     }
+
+    #[test]
+    fn spans() {
+        let span = |expr, operator, accesses| ExprSpan {
+            expr,
+            operator,
+            accesses,
+        };
+
+        let mut spans = Vec::new();
+        blockise(
+            tokenise("(1 + 2) * v.a; return v.a;", None, DEFAULT_MAX_DEPTH).unwrap(),
+            DEFAULT_MAX_DEPTH,
+            Some(&mut spans),
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                span(0..13, 8..9, vec![]),
+                span(1..6, 3..4, vec![]),
+                span(1..2, 1..2, vec![]),
+                span(5..6, 5..6, vec![]),
+                span(10..13, 10..13, vec![10..11, 12..13]),
+                span(15..25, 15..21, vec![]),
+                span(22..25, 22..25, vec![22..23, 24..25]),
+            ],
+            spans
+        );
+    }
 }
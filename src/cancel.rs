@@ -0,0 +1,86 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Aborts evaluation from outside, e.g. from another thread at the end of a tick.
+///
+/// Clones share one flag. Once cancelled, every evaluation using the handle
+/// returns [`crate::MolangError::Cancelled`] until [`CancelHandle::reset`].
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, time::Instant};
+
+    use crate::{MolangError, Runtime, Value};
+
+    use super::CancelHandle;
+
+    fn long_sum() -> String {
+        format!("{}1", "1 + ".repeat(100))
+    }
+
+    #[test]
+    fn cancel() {
+        let handle = CancelHandle::new();
+        let stop = handle.clone();
+
+        let mut runtime = Runtime::builder()
+            .cancel_handle(handle.clone())
+            .function("stop", move |_| {
+                stop.cancel();
+                Ok(Value::Null)
+            })
+            .variable("v", Value::Struct(HashMap::new()))
+            .build();
+
+        assert!(matches!(
+            runtime.eval_str(&format!("v.a = 1; stop(); v.b = {};", long_sum())),
+            Err(MolangError::Cancelled)
+        ));
+        assert_eq!(
+            Value::Struct(HashMap::from([("a".to_string(), Value::Number(1.0))])),
+            runtime.variables()["v"]
+        );
+
+        assert!(matches!(runtime.eval_str("1"), Err(MolangError::Cancelled)));
+        handle.reset();
+        assert_eq!(Value::Number(1.0), runtime.eval_str("1").unwrap());
+    }
+
+    #[test]
+    fn deadline() {
+        let mut runtime = Runtime::default();
+
+        runtime.set_deadline(Some(Instant::now()));
+        assert!(matches!(
+            runtime.eval_str(&long_sum()),
+            Err(MolangError::Cancelled)
+        ));
+
+        runtime.set_deadline(None);
+        assert_eq!(Value::Number(101.0), runtime.eval_str(&long_sum()).unwrap());
+    }
+}
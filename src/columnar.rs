@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+
+use crate::{
+    blockiser::Block,
+    limits::Budget,
+    parser::{AccessExpr, Instruction},
+    variables::{assign, PathSegment},
+    Expr, MolangError, Number, Value, VariableStore,
+};
+
+/// Rows evaluated together, sized so a program's registers stay in cache.
+const CHUNK: usize = 256;
+
+/// Per-row numeric inputs, e.g. one value per bone, keyed by full variable path
+/// such as `variable.offset`. Aliases are resolved before lookup.
+#[derive(Debug, Default, Clone)]
+pub struct Columns<'a> {
+    columns: HashMap<String, Column<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Column<'a> {
+    Slice(&'a [Number]),
+    Scalar(Number),
+}
+
+impl<'a> Columns<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn column(mut self, name: &str, values: &'a [Number]) -> Self {
+        self.columns.insert(name.to_string(), Column::Slice(values));
+        self
+    }
+
+    /// A value shared by every row.
+    pub fn scalar(mut self, name: &str, value: Number) -> Self {
+        self.columns.insert(name.to_string(), Column::Scalar(value));
+        self
+    }
+
+    fn get(&self, name: &str, row: usize) -> Option<Number> {
+        match self.columns.get(name)? {
+            Column::Slice(values) => values.get(row).copied(),
+            Column::Scalar(value) => Some(*value),
+        }
+    }
+
+    /// Checks every column has at least `rows` values.
+    pub(crate) fn check(&self, rows: usize) -> Result<(), MolangError> {
+        for (name, column) in &self.columns {
+            if let Column::Slice(values) = column {
+                if values.len() < rows {
+                    return Err(MolangError::BadAccess("[]".to_string(), name.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    Const(Number),
+    Load(String),
+    Add(usize, usize),
+    Subtract(usize, usize),
+    Multiply(usize, usize),
+    Divide(usize, usize),
+}
+
+/// A purely numeric block as a list of operations over whole columns. Each
+/// operation writes its own register, so operands always come before it.
+#[derive(Debug)]
+pub(crate) struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    /// Compiles a block of one arithmetic statement over numbers and columns,
+    /// or `None` if it needs the scalar interpreter. A block of statements only
+    /// gives the value of a `return`, so its statement must be one.
+    pub(crate) fn compile(
+        block: &Block,
+        constants: &HashMap<String, Value>,
+        columns: &Columns,
+    ) -> Option<Self> {
+        let [statement] = block.statements.as_slice() else {
+            return None;
+        };
+
+        let statement = match statement {
+            Expr::Derived(i) => match i.as_ref() {
+                Instruction::Return(expr) => expr,
+                _ if block.multiple => return None,
+                _ => statement,
+            },
+            Expr::Literal(_) if block.multiple => return None,
+            Expr::Literal(_) => statement,
+        };
+
+        let mut program = Program { ops: Vec::new() };
+        program.push_expr(statement, constants, columns)?;
+        Some(program)
+    }
+
+    fn push_expr(
+        &mut self,
+        expr: &Expr,
+        constants: &HashMap<String, Value>,
+        columns: &Columns,
+    ) -> Option<usize> {
+        let op = match expr {
+            Expr::Literal(Value::Number(n)) => Op::Const(*n),
+            Expr::Literal(_) => return None,
+            Expr::Derived(i) => match i.as_ref() {
+                Instruction::Access(accesses) => {
+                    let mut names = Vec::with_capacity(accesses.len());
+                    for access in accesses {
+                        match access {
+                            AccessExpr::Name(name) => names.push(name.as_str()),
+                            _ => return None,
+                        }
+                    }
+
+                    // Constants win over variables in the interpreter, so only
+                    // what would be a variable can come from a column.
+                    if constants.contains_key(*names.first()?) {
+                        return None;
+                    }
+
+                    let name = names.join(".");
+                    columns.columns.get(&name)?;
+                    Op::Load(name)
+                }
+                Instruction::Add(l, r)
+                | Instruction::Subtract(l, r)
+                | Instruction::Multiply(l, r)
+                | Instruction::Divide(l, r) => {
+                    let l = self.push_expr(l, constants, columns)?;
+                    let r = self.push_expr(r, constants, columns)?;
+                    match i.as_ref() {
+                        Instruction::Add(_, _) => Op::Add(l, r),
+                        Instruction::Subtract(_, _) => Op::Subtract(l, r),
+                        Instruction::Multiply(_, _) => Op::Multiply(l, r),
+                        _ => Op::Divide(l, r),
+                    }
+                }
+                _ => return None,
+            },
+        };
+
+        self.ops.push(op);
+        Some(self.ops.len() - 1)
+    }
+
+    /// Evaluates one chunk of rows at a time, running each operation across the
+    /// whole chunk so the arithmetic loops vectorise. `columns` must have passed
+    /// [`Columns::check`] for `out.len()` rows.
+    ///
+    /// Each operation counts as a step per row, charged to `budget` before the
+    /// chunk runs, which is also when cancellation and the deadline are seen.
+    pub(crate) fn run(
+        &self,
+        columns: &Columns,
+        out: &mut [Number],
+        budget: &mut Budget,
+    ) -> Result<(), MolangError> {
+        let mut registers = vec![[0.0; CHUNK]; self.ops.len()];
+
+        for (chunk, out) in out.chunks_mut(CHUNK).enumerate() {
+            let start = chunk * CHUNK;
+            let len = out.len();
+            budget.steps(self.ops.len() * len)?;
+
+            for (i, op) in self.ops.iter().enumerate() {
+                let (operands, rest) = registers.split_at_mut(i);
+                let dest = &mut rest[0][..len];
+
+                match op {
+                    Op::Const(n) => dest.fill(*n),
+                    Op::Load(name) => match columns.columns[name] {
+                        Column::Slice(values) => dest.copy_from_slice(&values[start..start + len]),
+                        Column::Scalar(value) => dest.fill(value),
+                    },
+                    Op::Add(l, r) => zip_with(dest, &operands[*l], &operands[*r], |l, r| l + r),
+                    Op::Subtract(l, r) => {
+                        zip_with(dest, &operands[*l], &operands[*r], |l, r| l - r)
+                    }
+                    Op::Multiply(l, r) => {
+                        zip_with(dest, &operands[*l], &operands[*r], |l, r| l * r)
+                    }
+                    Op::Divide(l, r) => zip_with(dest, &operands[*l], &operands[*r], |l, r| l / r),
+                }
+            }
+
+            if let Some(result) = registers.last() {
+                out.copy_from_slice(&result[..len]);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[inline(always)]
+fn zip_with(dest: &mut [Number], l: &[Number], r: &[Number], f: impl Fn(Number, Number) -> Number) {
+    for ((dest, l), r) in dest.iter_mut().zip(l).zip(r) {
+        *dest = f(*l, *r);
+    }
+}
+
+/// Serves one row of `columns` as variables to the scalar interpreter. Writes
+/// land in a scratch map, which only lives for the row, and hide a column
+/// only once they reach its path.
+pub(crate) struct Row<'a> {
+    pub columns: &'a Columns<'a>,
+    pub row: usize,
+    pub scratch: HashMap<String, Value>,
+}
+
+impl VariableStore for Row<'_> {
+    fn get(&self, name: &str) -> Option<Value> {
+        match self.scratch.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.columns.get(name, self.row).map(Value::Number),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), MolangError> {
+        self.scratch.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn get_path(&self, name: &str, path: &[&str]) -> Result<Value, MolangError> {
+        let written = self
+            .scratch
+            .get(name)
+            .is_some_and(|root| written(root, path));
+        if written {
+            return self.scratch.get_path(name, path);
+        }
+
+        let key = std::iter::once(name)
+            .chain(path.iter().copied())
+            .collect::<Vec<_>>()
+            .join(".");
+
+        match self.columns.get(&key, self.row) {
+            Some(value) => Ok(Value::Number(value)),
+            None if self.scratch.contains_key(name) => self.scratch.get_path(name, path),
+            None => Err(MolangError::VariableNotFound(key)),
+        }
+    }
+
+    fn set_path(
+        &mut self,
+        name: &str,
+        path: &[PathSegment],
+        value: Value,
+    ) -> Result<(), MolangError> {
+        let root = self
+            .scratch
+            .entry(name.to_string())
+            .or_insert_with(|| Value::Struct(HashMap::new()));
+
+        if path.is_empty() {
+            *root = value;
+            Ok(())
+        } else {
+            assign(root, path, value)
+        }
+    }
+
+    fn struct_len(&self, name: &str, path: &[&str]) -> Option<usize> {
+        self.scratch.struct_len(name, path)
+    }
+
+    fn has_member(&self, name: &str, path: &[&str]) -> bool {
+        self.scratch.has_member(name, path)
+    }
+}
+
+/// Whether `path` within `root` has been written, or reads from a value which
+/// was written whole.
+fn written(root: &Value, path: &[&str]) -> bool {
+    match (root, path) {
+        (Value::Struct(members), [member, rest @ ..]) => members
+            .get(*member)
+            .is_some_and(|member| written(member, rest)),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, time::Instant};
+
+    use crate::{CancelHandle, Limit, Limits, MolangError, Number, Runtime, Value};
+
+    use super::{Columns, Program};
+
+    fn runtime() -> Runtime {
+        Runtime::builder()
+            .constant("speed", 40.0)
+            .alias("q", "query")
+            .alias("v", "variable")
+            .build()
+    }
+
+    fn eval(source: &str, columns: &Columns, rows: usize) -> Result<Vec<Number>, MolangError> {
+        let runtime = runtime();
+        let mut out = vec![0.0; rows];
+        runtime.eval_columns(&runtime.compile(source)?, columns, &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn numeric() {
+        let offsets: Vec<Number> = (0..1000).map(|i| i as Number).collect();
+        let columns = Columns::new()
+            .scalar("query.anim_time", 2.0)
+            .column("variable.offset", &offsets);
+
+        let out = eval("q.anim_time * speed + v.offset", &columns, 1000).unwrap();
+
+        assert_eq!(
+            offsets.iter().map(|o| 2.0 * 40.0 + o).collect::<Vec<_>>(),
+            out
+        );
+
+        let runtime = runtime();
+        let block = runtime.prepare(&runtime.compile("return (v.offset - 1) / 2;").unwrap());
+        assert!(Program::compile(&block, runtime.constants(), &columns).is_some());
+    }
+
+    #[test]
+    fn fallback() {
+        let offsets = [0.0, 1.0, 2.0];
+        let columns = Columns::new().column("variable.offset", &offsets);
+
+        let runtime = runtime();
+        let block = runtime.prepare(
+            &runtime
+                .compile("t.x = v.offset * 2; return v.offset ? t.x : 10;")
+                .unwrap(),
+        );
+        assert!(Program::compile(&block, runtime.constants(), &columns).is_none());
+
+        assert_eq!(
+            vec![10.0, 2.0, 4.0],
+            eval(
+                "t.x = v.offset * 2; return v.offset ? t.x : 10;",
+                &columns,
+                3
+            )
+            .unwrap()
+        );
+        assert!(matches!(
+            eval("v.missing + 1", &columns, 3),
+            Err(MolangError::VariableNotFound(_))
+        ));
+        assert!(matches!(
+            eval("v.offset", &columns, 4),
+            Err(MolangError::BadAccess(_, _))
+        ));
+    }
+
+    #[test]
+    fn writes_keep_columns() {
+        let offsets = [1.0, 2.0];
+        let columns = Columns::new().column("variable.offset", &offsets);
+
+        assert_eq!(
+            vec![3.0, 6.0],
+            eval("v.y = v.offset * 2; return v.y + v.offset;", &columns, 2).unwrap()
+        );
+        assert_eq!(
+            vec![5.0, 5.0],
+            eval("v.offset = 5; return v.offset;", &columns, 2).unwrap()
+        );
+    }
+
+    #[test]
+    fn statements_without_return() {
+        let offsets = [1.0, 2.0, 3.0];
+        let columns = Columns::new().column("variable.offset", &offsets);
+
+        let runtime = runtime();
+        let block = runtime.compile("v.offset;").unwrap();
+        assert!(
+            Program::compile(&runtime.prepare(&block), runtime.constants(), &columns).is_none()
+        );
+
+        let out = eval("v.offset;", &columns, 3).unwrap();
+        for (offset, out) in offsets.iter().zip(out) {
+            let mut variables = HashMap::from([(
+                "variable".to_string(),
+                Value::Struct(HashMap::from([(
+                    "offset".to_string(),
+                    Value::Number(*offset),
+                )])),
+            )]);
+            assert_eq!(
+                runtime.eval_with(&block, &mut variables).unwrap(),
+                Value::Number(out)
+            );
+        }
+    }
+
+    #[test]
+    fn vectorised_budget() {
+        let offsets = [1.0; 10];
+        let columns = Columns::new().column("variable.offset", &offsets);
+        let eval = |runtime: &mut Runtime| {
+            let block = runtime.compile("variable.offset + 1").unwrap();
+            assert!(
+                Program::compile(&runtime.prepare(&block), runtime.constants(), &columns).is_some()
+            );
+            runtime.eval_columns(&block, &columns, &mut [0.0; 10])
+        };
+        let limited = |max_steps| {
+            Runtime::builder()
+                .limits(Limits {
+                    max_steps: Some(max_steps),
+                    ..Default::default()
+                })
+                .build()
+        };
+
+        // A load, a constant and an add for each of 10 rows.
+        assert!(eval(&mut limited(30)).is_ok());
+        assert!(matches!(
+            eval(&mut limited(29)),
+            Err(MolangError::LimitExceeded(Limit::Steps))
+        ));
+
+        let cancel = CancelHandle::new();
+        let mut runtime = Runtime::builder().cancel_handle(cancel.clone()).build();
+        cancel.cancel();
+        assert!(matches!(eval(&mut runtime), Err(MolangError::Cancelled)));
+
+        let mut runtime = Runtime::default();
+        runtime.set_deadline(Some(Instant::now()));
+        assert!(matches!(eval(&mut runtime), Err(MolangError::Cancelled)));
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use crate::{
+    blockiser::Block,
+    parser::{AccessExpr, Instruction},
+    reachability::{colon, constant, unreachable_code, Unreachable},
+    Expr, Value,
+};
+
+/// What the host's queries and functions cost, for [`estimate_cost`], by full
+/// path such as `query.anim_time` or `math.max`.
+///
+/// Costs are in the steps [`crate::Limits::max_steps`] counts, one for each
+/// expression evaluated, so a query as slow as evaluating ten expressions
+/// costs `10`.
+#[derive(Debug, Default, Clone)]
+pub struct CostModel {
+    queries: HashMap<String, u64>,
+    functions: HashMap<String, u64>,
+    unknown: u64,
+}
+
+impl CostModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Each read of, or call to, a query.
+    pub fn query(mut self, name: &str, cost: u64) -> Self {
+        self.queries.insert(name.to_string(), cost);
+        self
+    }
+
+    /// Each call to a function.
+    pub fn function(mut self, name: &str, weight: u64) -> Self {
+        self.functions.insert(name.to_string(), weight);
+        self
+    }
+
+    /// Each call, or read of a `query`, which is not declared. Nothing by default.
+    pub fn unknown(mut self, cost: u64) -> Self {
+        self.unknown = cost;
+        self
+    }
+}
+
+/// The most `block` can cost in one run under `model`: every reachable
+/// statement, the dearer branch of each ternary and the one a constant
+/// condition takes. Without declared costs this is the most steps the run
+/// can take.
+pub fn estimate_cost(
+    block: &Block,
+    model: &CostModel,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> u64 {
+    let reachable = unreachable_code(block, constants, aliases)
+        .into_iter()
+        .find_map(|unreachable| match unreachable {
+            Unreachable::Statement(index) => Some(index),
+            Unreachable::Branch { .. } => None,
+        })
+        .unwrap_or(block.statements.len());
+
+    let estimator = Estimator {
+        model,
+        constants,
+        aliases,
+    };
+
+    block.statements[..reachable]
+        .iter()
+        .fold(0, |cost, statement| {
+            cost.saturating_add(estimator.expr(statement))
+        })
+}
+
+struct Estimator<'a> {
+    model: &'a CostModel,
+    constants: &'a HashMap<String, Value>,
+    aliases: &'a HashMap<String, String>,
+}
+
+impl Estimator<'_> {
+    fn expr(&self, expr: &Expr) -> u64 {
+        let instruction = match expr {
+            Expr::Literal(_) => return 1,
+            Expr::Derived(instruction) => instruction.as_ref(),
+        };
+
+        let children = match instruction {
+            Instruction::Add(left, right)
+            | Instruction::Subtract(left, right)
+            | Instruction::Multiply(left, right)
+            | Instruction::Divide(left, right)
+            | Instruction::Equality(left, right)
+            | Instruction::NullishCoalescing(left, right) => {
+                self.expr(left).saturating_add(self.expr(right))
+            }
+            Instruction::Not(expr) | Instruction::Return(expr) => self.expr(expr),
+            // The target is not evaluated, only its indices.
+            Instruction::Assignment(left, right) => {
+                let indices = match left {
+                    Expr::Derived(i) => match i.as_ref() {
+                        Instruction::Access(accesses) => self.arguments(accesses),
+                        _ => 0,
+                    },
+                    Expr::Literal(_) => 0,
+                };
+                indices.saturating_add(self.expr(right))
+            }
+            Instruction::Conditional(condition, branches) => {
+                let branches = match (
+                    constant(condition, self.constants, self.aliases),
+                    colon(branches),
+                ) {
+                    (Some(true), Some((if_true, _))) => self.expr(if_true),
+                    (Some(false), Some((_, if_false))) => self.expr(if_false),
+                    (None, Some((if_true, if_false))) => {
+                        self.expr(if_true).max(self.expr(if_false))
+                    }
+                    // Fails before running either.
+                    (_, None) => 0,
+                };
+                self.expr(condition).saturating_add(branches)
+            }
+            Instruction::Colon(_, _) => 0,
+            Instruction::Access(accesses) => self
+                .arguments(accesses)
+                .saturating_add(self.calls(accesses)),
+        };
+
+        children.saturating_add(1)
+    }
+
+    /// The indices and call arguments of an access.
+    fn arguments(&self, accesses: &[AccessExpr]) -> u64 {
+        accesses.iter().fold(0, |cost, access| {
+            cost.saturating_add(match access {
+                AccessExpr::Index(index) => self.expr(index),
+                AccessExpr::Call(args) => args
+                    .iter()
+                    .fold(0, |cost, arg| cost.saturating_add(self.expr(arg))),
+                AccessExpr::Name(_) | AccessExpr::Slot(_) => 0,
+            })
+        })
+    }
+
+    /// The declared cost of the query or functions an access reaches.
+    fn calls(&self, accesses: &[AccessExpr]) -> u64 {
+        let mut path = String::new();
+        let mut rest = accesses.iter();
+        let next = loop {
+            match rest.next() {
+                Some(AccessExpr::Name(name)) if path.is_empty() => {
+                    path.push_str(self.aliases.get(name.as_str()).map_or(name, String::as_str));
+                }
+                Some(AccessExpr::Name(name)) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                next => break next,
+            }
+        };
+
+        let called = matches!(next, Some(AccessExpr::Call(_)));
+        let cost = if let Some(cost) = self.model.queries.get(&path) {
+            *cost
+        } else if called {
+            self.model
+                .functions
+                .get(&path)
+                .copied()
+                .unwrap_or(self.model.unknown)
+        } else if path.starts_with("query.") {
+            self.model.unknown
+        } else {
+            0
+        };
+
+        // Calls on what the first returns are never declared.
+        let further = rest
+            .filter(|access| matches!(access, AccessExpr::Call(_)))
+            .count() as u64;
+
+        cost.saturating_add(further.saturating_mul(self.model.unknown))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{compile, Limits, MolangError, Runtime, Value};
+
+    use super::{estimate_cost, CostModel};
+
+    #[test]
+    fn matches_steps() {
+        let source = "v.a = q.x ? max(1, 2) : 3; v.b = v.a == 1; return v.a + !1;";
+        let estimate = estimate_cost(
+            &compile(source).unwrap(),
+            &CostModel::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+        assert_eq!(15, estimate);
+
+        let run = |max_steps: u64| {
+            Runtime::builder()
+                .query_provider("q", |_: &str, _: Vec<Value>| Ok(Value::Number(1.0)))
+                .function("max", |args| Ok(args[0].clone()))
+                .variable("v", Value::Struct(HashMap::new()))
+                .limits(Limits {
+                    max_steps: Some(max_steps as usize),
+                    ..Default::default()
+                })
+                .build()
+                .eval_str(source)
+        };
+        assert!(!matches!(run(estimate), Err(MolangError::LimitExceeded(_))));
+        assert!(matches!(
+            run(estimate - 1),
+            Err(MolangError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn model() {
+        let runtime = Runtime::builder()
+            .alias("q", "query")
+            .constant("debug", Value::Number(0.0))
+            .build();
+        let model = CostModel::new()
+            .query("query.anim_time", 10)
+            .query("query.bone", 20)
+            .function("math.sin", 5)
+            .unknown(100);
+
+        let cost = |source| runtime.estimate_cost(&runtime.compile(source).unwrap(), &model);
+
+        // Access and argument nodes, then the query and function costs.
+        assert_eq!(2 + 10 + 5, cost("math.sin(q.anim_time)"));
+        assert_eq!(2 + 20, cost("q.bone(\"head\")"));
+        assert_eq!(1 + 100, cost("query.unknown"));
+        assert_eq!(1 + 100, cost("v.f()"));
+        assert_eq!(1, cost("v.x"));
+
+        // The dearer branch, unless the condition is constant.
+        assert_eq!(3 + 10, cost("v.x ? q.anim_time : 1"));
+        assert_eq!(3, cost("debug ? q.anim_time : 1"));
+
+        // Nothing after an unconditional `return`.
+        assert_eq!(2, cost("return 1; q.anim_time;"));
+    }
+}
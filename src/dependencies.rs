@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    blockiser::Block,
+    parser::{AccessExpr, Instruction},
+    visitor::{walk_access, walk_instruction, Visitor},
+    Expr,
+};
+
+/// What a script touches, as full paths with aliases resolved, such as
+/// `query.anim_time` for `q.anim_time`. A path stops at the first index or
+/// call, so `v.list[0].x` reads `variable.list`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Dependencies {
+    pub reads: BTreeSet<String>,
+    pub writes: BTreeSet<String>,
+    /// Every function called, with each number of arguments it is called with.
+    pub calls: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl Dependencies {
+    /// The names read or called under `root`, e.g. the queries a script needs
+    /// for `"query"`.
+    pub fn under<'a>(&'a self, root: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.reads
+            .iter()
+            .chain(self.calls.keys())
+            .filter_map(move |path| path.strip_prefix(root)?.strip_prefix('.'))
+    }
+}
+
+/// Lists the variables `block` reads and writes and the functions it calls,
+/// without running it. Every path is reported, wherever the block may return.
+pub fn dependencies(block: &Block, aliases: &HashMap<String, String>) -> Dependencies {
+    let mut collect = Collect {
+        aliases,
+        dependencies: Dependencies::default(),
+        writing: false,
+    };
+    collect.visit_block(block);
+    collect.dependencies
+}
+
+struct Collect<'a> {
+    aliases: &'a HashMap<String, String>,
+    dependencies: Dependencies,
+    /// Set while visiting the access an assignment writes to.
+    writing: bool,
+}
+
+impl Visitor for Collect<'_> {
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Assignment(left, right) => {
+                self.writing =
+                    matches!(left, Expr::Derived(i) if matches!(**i, Instruction::Access(_)));
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            _ => walk_instruction(self, instruction),
+        }
+    }
+
+    fn visit_access(&mut self, accesses: &[AccessExpr]) {
+        let writing = std::mem::take(&mut self.writing);
+
+        let mut path = String::new();
+        let mut rest = accesses.iter();
+        let next = loop {
+            match rest.next() {
+                Some(AccessExpr::Name(name)) if path.is_empty() => {
+                    path.push_str(self.aliases.get(name.as_str()).map_or(name, String::as_str));
+                }
+                Some(AccessExpr::Name(name)) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                next => break next,
+            }
+        };
+
+        if !path.is_empty() {
+            match next {
+                Some(AccessExpr::Call(args)) => {
+                    self.dependencies
+                        .calls
+                        .entry(path)
+                        .or_default()
+                        .insert(args.len());
+                }
+                _ if writing => {
+                    self.dependencies.writes.insert(path);
+                }
+                _ => {
+                    self.dependencies.reads.insert(path);
+                }
+            }
+        }
+
+        walk_access(self, accesses);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{compile, Runtime};
+
+    use super::dependencies;
+
+    #[test]
+    fn reads_writes_calls() {
+        let runtime = Runtime::builder()
+            .alias("q", "query")
+            .alias("v", "variable")
+            .alias("t", "temp")
+            .build();
+
+        let block = runtime
+            .compile(
+                "t.x = q.anim_time * v.speed; v.list[q.index] = math.max(t.x, q.f(1), 2); \
+                 return query.is_moving ? math.max(1, v.list[0].y) : 0;",
+            )
+            .unwrap();
+        let deps = runtime.dependencies(&block);
+
+        assert_eq!(
+            vec![
+                "query.anim_time",
+                "query.index",
+                "query.is_moving",
+                "temp.x",
+                "variable.list",
+                "variable.speed"
+            ],
+            deps.reads.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["temp.x", "variable.list"],
+            deps.writes.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![("math.max", vec![2, 3]), ("query.f", vec![1])],
+            deps.calls
+                .iter()
+                .map(|(name, arities)| (name.as_str(), arities.iter().copied().collect()))
+                .collect::<Vec<(_, Vec<_>)>>()
+        );
+        assert_eq!(
+            vec!["anim_time", "index", "is_moving", "f"],
+            deps.under("query").collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn no_aliases() {
+        let deps = dependencies(&compile("q.a = 1 ?? q.b").unwrap(), &HashMap::new());
+
+        assert_eq!(vec!["q.b"], deps.reads.iter().collect::<Vec<_>>());
+        assert_eq!(vec!["q.a"], deps.writes.iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{
+    data::Operator,
+    tokeniser::{lex, SourceKind, SourceToken, TokeniseError},
+    CompileError,
+};
+
+/// Statements are only split onto lines past this many characters, unless
+/// comments or blocks need them to be.
+const WIDTH: usize = 80;
+const INDENT: &str = "    ";
+
+enum Node<'a> {
+    Token {
+        kind: SourceKind,
+        text: &'a str,
+        /// Whether whitespace came before it in the source.
+        spaced: bool,
+    },
+    Comment {
+        kind: SourceKind,
+        text: &'a str,
+        own_line: bool,
+    },
+    Block(Vec<Statement<'a>>),
+}
+
+#[derive(Default)]
+struct Statement<'a> {
+    nodes: Vec<Node<'a>>,
+    /// Whether a blank line came before it in the source.
+    blank_before: bool,
+}
+
+impl Statement<'_> {
+    /// Whether it holds a comment which must end or start a line.
+    fn needs_lines(&self) -> bool {
+        self.nodes.iter().any(|node| {
+            matches!(
+                node,
+                Node::Comment {
+                    kind: SourceKind::LineComment,
+                    ..
+                } | Node::Comment { own_line: true, .. }
+            )
+        })
+    }
+}
+
+/// Groups tokens into statements and `{}` blocks, dropping whitespace but
+/// remembering where it held line breaks.
+struct Parser<'a> {
+    tokens: std::vec::IntoIter<SourceToken<'a>>,
+    newlines: usize,
+    spaced: bool,
+    started: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn statements(&mut self, nested: bool) -> Result<Vec<Statement<'a>>, TokeniseError> {
+        let mut statements: Vec<Statement> = Vec::new();
+        let mut current = Statement::default();
+        let mut brackets: usize = 0;
+
+        loop {
+            let Some(token) = self.tokens.next() else {
+                if nested {
+                    return Err(TokeniseError::Expectation {
+                        found: "EOF".to_string(),
+                        expected: "}".to_string(),
+                    });
+                }
+                break;
+            };
+
+            if token.kind == SourceKind::Whitespace {
+                // Line breaks before the first token are dropped.
+                if self.started {
+                    self.newlines += token.text.matches('\n').count();
+                }
+                self.spaced = true;
+                continue;
+            }
+
+            self.started = true;
+            let newlines = std::mem::take(&mut self.newlines);
+            let spaced = std::mem::take(&mut self.spaced);
+
+            let node = match token.kind {
+                SourceKind::LineComment | SourceKind::BlockComment => {
+                    let comment = Node::Comment {
+                        kind: token.kind,
+                        text: token.text,
+                        own_line: newlines > 0,
+                    };
+
+                    // A comment on the same line as the end of a statement
+                    // belongs to that statement.
+                    match statements.last_mut() {
+                        Some(last) if current.nodes.is_empty() && newlines == 0 => {
+                            last.nodes.push(comment);
+                            continue;
+                        }
+                        _ => comment,
+                    }
+                }
+                SourceKind::OpenBrace => Node::Block(self.statements(true)?),
+                SourceKind::CloseBrace if nested => break,
+                SourceKind::CloseBrace => {
+                    return Err(TokeniseError::Expectation {
+                        found: "}".to_string(),
+                        expected: "anything else".to_string(),
+                    })
+                }
+                kind => Node::Token {
+                    kind,
+                    text: token.text,
+                    spaced,
+                },
+            };
+
+            if current.nodes.is_empty() {
+                current.blank_before = newlines > 1;
+            }
+            current.nodes.push(node);
+
+            match token.kind {
+                SourceKind::OpenBracket | SourceKind::OpenIndex => brackets += 1,
+                SourceKind::CloseBracket | SourceKind::CloseIndex => {
+                    brackets = brackets.saturating_sub(1)
+                }
+                SourceKind::Semicolon if brackets == 0 => {
+                    statements.push(std::mem::take(&mut current));
+                }
+                _ => {}
+            }
+        }
+
+        if !current.nodes.is_empty() {
+            statements.push(current);
+        }
+        Ok(statements)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    depth: usize,
+    /// The last thing written on the current line, `None` at its start.
+    prev: Option<SourceKind>,
+    after_line_comment: bool,
+}
+
+impl Writer {
+    fn newline(&mut self, blank: bool) {
+        if blank {
+            self.out.push('\n');
+        }
+        self.out.push('\n');
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.prev = None;
+        self.after_line_comment = false;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        for node in &statement.nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        if self.after_line_comment {
+            self.newline(false);
+        }
+
+        match node {
+            Node::Token { kind, text, spaced } => {
+                if self.prev.is_some_and(|prev| space(prev, *kind, *spaced)) {
+                    self.out.push(' ');
+                }
+                self.out.push_str(text);
+                self.prev = Some(*kind);
+            }
+            Node::Comment {
+                kind,
+                text,
+                own_line,
+            } => {
+                if *own_line && self.prev.is_some() {
+                    self.newline(false);
+                } else if self.prev.is_some_and(|prev| !opens(prev)) {
+                    self.out.push(' ');
+                }
+                self.out.push_str(text.trim_end());
+                self.prev = Some(*kind);
+                self.after_line_comment = *kind == SourceKind::LineComment;
+            }
+            Node::Block(statements) => {
+                if self.prev.is_some_and(|prev| !opens(prev)) {
+                    self.out.push(' ');
+                }
+                self.out.push('{');
+
+                if !statements.is_empty() {
+                    self.depth += 1;
+                    for (i, statement) in statements.iter().enumerate() {
+                        self.newline(i > 0 && statement.blank_before);
+                        self.statement(statement);
+                    }
+                    self.depth -= 1;
+                    self.newline(false);
+                }
+
+                self.out.push('}');
+                self.prev = Some(SourceKind::CloseBrace);
+            }
+        }
+    }
+}
+
+/// Whatever follows these starts without a space.
+fn opens(kind: SourceKind) -> bool {
+    matches!(
+        kind,
+        SourceKind::OpenBracket
+            | SourceKind::OpenIndex
+            | SourceKind::Dot
+            | SourceKind::Operator(Operator::Not)
+    )
+}
+
+fn space(prev: SourceKind, next: SourceKind, spaced: bool) -> bool {
+    use SourceKind::*;
+
+    match (prev, next) {
+        (Identifier | CloseBracket | CloseIndex, Dot | OpenBracket | OpenIndex) => false,
+        // Dropping the space in `a. b` or `1 .5` would change their meaning.
+        (Dot, _) | (_, Dot) => spaced,
+        (_, CloseBracket | CloseIndex | Comma | Semicolon) => false,
+        (prev, _) => !opens(prev),
+    }
+}
+
+/// Reformats a script: one space around binary operators and after commas,
+/// none inside brackets, and `{}` blocks indented with a statement per line.
+/// Statements at the top level share a line while they fit in 80 columns.
+/// Comments and blank lines between statements are kept.
+pub fn format_source(source: &str) -> Result<String, CompileError> {
+    let tokens = lex(source).map_err(CompileError::TokeniseError)?;
+    let statements = Parser {
+        tokens: tokens.into_iter(),
+        newlines: 0,
+        spaced: false,
+        started: false,
+    }
+    .statements(false)
+    .map_err(CompileError::TokeniseError)?;
+
+    let flat: Vec<String> = statements
+        .iter()
+        .map(|statement| {
+            let mut writer = Writer::default();
+            writer.statement(statement);
+            writer.out
+        })
+        .collect();
+
+    let width = flat.iter().map(|s| s.chars().count() + 1).sum::<usize>();
+    let split = statements.len() > 1
+        && (width > WIDTH + 1
+            || flat.iter().any(|s| s.contains('\n'))
+            || statements.iter().any(Statement::needs_lines));
+
+    let mut writer = Writer::default();
+    for (i, statement) in statements.iter().enumerate() {
+        if i > 0 && split {
+            writer.newline(statement.blank_before);
+        }
+        writer.statement(statement);
+    }
+
+    Ok(writer.out)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compile;
+
+    use super::format_source;
+
+    fn format(source: &str) -> String {
+        format_source(source).unwrap()
+    }
+
+    #[test]
+    fn spacing() {
+        assert_eq!("1 + 2 * 3", format("1+2  *3"));
+        assert_eq!(
+            "v.x = math.max(q.a, 2) ?? !v.y[0];",
+            format(" v .x=math.max ( q.a ,2 )??! v.y [ 0 ] ;")
+        );
+        assert_eq!("return (1 + 2) / 3;", format("return(1+2)/3;"));
+        assert_eq!("a ? b : c", format("a?b:c"));
+        assert_eq!("a. b", format("a. b"));
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!("t.a = 1; return t.a;", format("t.a=1;return t.a;"));
+
+        let long =
+            "variable.first_long_name = 1; variable.second_long_name = 2; variable.third_name = 3;";
+        assert_eq!(
+            "variable.first_long_name = 1;\nvariable.second_long_name = 2;\nvariable.third_name = 3;",
+            format(long)
+        );
+
+        assert_eq!(
+            "q.a ? {\n    t.a = 1;\n\n    t.b = {};\n} : 0;",
+            format("q.a?{t.a=1;\n\n\n  t.b={};}:0;")
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            "// setup\nt.a = 1; // one\n/* two */ t.b = 2;\nreturn t.a /* a */ + t.b;",
+            format("// setup\nt.a=1;   // one  \n  /* two */t.b=2; return t.a/* a */+t.b;")
+        );
+        assert_eq!("{\n    // empty\n}", format("{// empty\n}"));
+    }
+
+    #[test]
+    fn idempotent() {
+        for source in [
+            "1+2  *3",
+            "t.a=1;// one\nt.b = 2;\n\n\nreturn t.a+t.b;",
+            "q.a?{t.a=1;/* x */ {t.b=2;}\n// end\n}:0;",
+            "q.a?{return 1;}:{  }",
+            "a+\n// b\nb",
+            "a. b;1 .5",
+            "v.first_long_name = query.something(1, 2, 3); v.second_long_name = 2; return v.first_long_name;",
+        ] {
+            let formatted = format(source);
+            assert_eq!(formatted, format(&formatted), "{source}");
+            assert_eq!(compile(source).ok(), compile(&formatted).ok(), "{source}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for source in ["}", "{", "\"a", "/* a", "$"] {
+            assert!(format_source(source).is_err(), "{source}");
+        }
+    }
+}
@@ -4,13 +4,16 @@ use thiserror::Error;
 
 use crate::{
     blockiser::Block,
+    limits::{Budget, Limit, Limits},
     parser::{AccessExpr, Instruction},
-    Expr, External, Value,
+    sync::{lock, Shared},
+    variables::{assign, member, struct_len, PathSegment, VariableStore},
+    CompileError, Expr, External, Number, Symbol, Value,
 };
 
 macro_rules! run_bubble_returns {
-    ($expr:ident, $constants:ident, $variables:ident, $aliases:ident) => {
-        match run_expr($expr, $constants, $variables, $aliases)? {
+    ($expr:ident, $scope:ident) => {
+        match run_expr($expr, $scope)? {
             (rv, true) => return Ok((rv, true)),
             a => a.0,
         }
@@ -39,331 +42,566 @@ pub enum MolangError {
 
     #[error("Cannot access values of `{1}` by `{0}`")]
     BadAccess(String, String),
+
+    #[error("Compile error: {0}")]
+    CompileError(#[from] CompileError),
+
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(Limit),
+
+    #[error("Evaluation cancelled")]
+    Cancelled,
+
+    #[error("Panicked: {0}")]
+    Panicked(String),
+}
+
+pub struct Scope<'a> {
+    pub constants: &'a HashMap<String, Value>,
+    pub variables: &'a mut dyn VariableStore,
+    pub aliases: &'a HashMap<String, String>,
+    pub slots: &'a mut [Value],
+    pub budget: Budget<'a>,
 }
 
 pub fn run_block(
     block: &Block,
     constants: &HashMap<String, Value>,
-    variables: &mut HashMap<String, Value>,
+    variables: &mut dyn VariableStore,
     aliases: &HashMap<String, String>,
 ) -> Result<Value, MolangError> {
+    run_scoped(
+        block,
+        &mut Scope {
+            constants,
+            variables,
+            aliases,
+            slots: &mut [],
+            budget: Budget::new(&Limits::default()),
+        },
+    )
+}
+
+/// Runs a block produced by [`crate::link`], reading and writing names through
+/// `slots`. [`Runtime::eval_linked`](crate::Runtime::eval_linked) also applies
+/// a runtime's cancel handle and deadline.
+pub fn run_linked(
+    block: &Block,
+    slots: &mut [Value],
+    limits: &Limits,
+) -> Result<Value, MolangError> {
+    run_scoped(
+        block,
+        &mut Scope {
+            constants: &HashMap::new(),
+            variables: &mut HashMap::new(),
+            aliases: &HashMap::new(),
+            slots,
+            budget: Budget::new(limits),
+        },
+    )
+}
+
+pub fn run_scoped(block: &Block, scope: &mut Scope) -> Result<Value, MolangError> {
     if block.multiple {
         for statement in &block.statements {
-            match run_expr(&statement, constants, variables, aliases)? {
-                (rv, true) => return Ok(rv),
-                (_, _) => {}
+            if let (rv, true) = run_expr(statement, scope)? {
+                return Ok(rv);
             }
         }
         Ok(Value::Number(0.0))
     } else {
-        Ok(run_expr(&block.statements[0], constants, variables, aliases)?.0)
+        match block.statements.first() {
+            Some(statement) => Ok(run_expr(statement, scope)?.0),
+            None => Ok(Value::Number(0.0)),
+        }
     }
 }
 
-pub fn run_expr(
-    expr: &Expr,
-    constants: &HashMap<String, Value>,
-    variables: &mut HashMap<String, Value>,
-    aliases: &HashMap<String, String>,
-) -> Result<(Value, bool), MolangError> {
-    match expr {
-        Expr::Literal(expr) => Ok((expr.clone(), false)),
-        Expr::Derived(i) => {
-            let i = i.as_ref();
-            match i {
-                Instruction::Add(left, right)
-                | Instruction::Subtract(left, right)
-                | Instruction::Multiply(left, right)
-                | Instruction::Divide(left, right) => {
-                    let left = match run_bubble_returns!(left, constants, variables, aliases) {
-                        Value::Number(n) => n,
-                        a => {
-                            return Err(MolangError::TypeError(
-                                "Number".to_string(),
-                                format!("{a:?}"),
-                            ))
-                        }
-                    };
-                    let right = match run_bubble_returns!(right, constants, variables, aliases) {
-                        Value::Number(n) => n,
-                        a => {
-                            return Err(MolangError::TypeError(
-                                "Number".to_string(),
-                                format!("{a:?}"),
-                            ))
-                        }
-                    };
-                    Ok((
-                        Value::Number(match i {
-                            Instruction::Add(_, _) => left + right,
-                            Instruction::Subtract(_, _) => left - right,
-                            Instruction::Multiply(_, _) => left * right,
-                            Instruction::Divide(_, _) => left / right,
-                            _ => unreachable!(),
-                        }),
-                        false,
-                    ))
-                }
-                Instruction::Access(accesses) => {
-                    let mut current = Value::Null;
-
-                    let mut last_external: Option<(
-                        std::rc::Rc<std::cell::RefCell<dyn External>>,
-                        &String,
-                    )> = None;
-
-                    for access in accesses {
-                        match access {
-                            AccessExpr::Call(args) => {
-                                if let Some(ref last_external) = last_external {
-                                    let mut v_args = Vec::new();
-
-                                    for arg in args {
-                                        v_args.push(run_bubble_returns!(
-                                            arg, constants, variables, aliases
-                                        ));
-                                    }
-
-                                    current = last_external
-                                        .0
-                                        .borrow_mut()
-                                        .call_function(last_external.1, v_args)?;
-                                    continue;
-                                }
-                            }
-                            _ => {}
-                        }
+pub fn run_expr(mut expr: &Expr, scope: &mut Scope) -> Result<(Value, bool), MolangError> {
+    // Branches taken are run in this loop rather than by recursing, so a long
+    // `a ? b : c ? d : e` chain stays off the end of the stack.
+    loop {
+        scope.budget.step()?;
+
+        let i = match expr {
+            Expr::Literal(expr) => return Ok((expr.clone(), false)),
+            Expr::Derived(i) => i.as_ref(),
+        };
+
+        return match i {
+            Instruction::Add(_, _)
+            | Instruction::Subtract(_, _)
+            | Instruction::Multiply(_, _)
+            | Instruction::Divide(_, _)
+            | Instruction::Equality(_, _) => run_chain(i, scope),
+            Instruction::Access(accesses) => run_access(accesses, scope),
+            Instruction::Assignment(left, right) => run_assignment(left, right, scope),
+            Instruction::Conditional(left, right) => {
+                let left = match run_bubble_returns!(left, scope) {
+                    Value::Number(n) => n,
+                    a => {
+                        return Err(MolangError::TypeError(
+                            "Number".to_string(),
+                            format!("{a:?}"),
+                        ))
+                    }
+                };
 
-                        last_external = None;
-
-                        match access {
-                            AccessExpr::Name(name) => {
-                                let mut name = name;
-                                if let Value::Null = current {
-                                    if let Some(alias) = aliases.get(name) {
-                                        name = alias;
-                                    }
-
-                                    current = constants
-                                        .get(name)
-                                        .or(variables.get(name))
-                                        .ok_or_else(|| {
-                                            MolangError::VariableNotFound(name.to_string())
-                                        })?
-                                        .clone();
-                                } else if let Value::Struct(struc) = current {
-                                    current = struc.get(name).unwrap_or(&Value::Null).clone();
-                                } else if let Value::External(e) = current {
-                                    current = e.borrow_mut().get(name);
-                                    last_external = Some((e.clone(), name));
-                                } else {
-                                    return Err(MolangError::BadAccess(
-                                        ".".to_string(),
-                                        format!("{current:?}"),
-                                    ));
-                                }
-                            }
-                            AccessExpr::Index(idx) => {
-                                if let Value::External(e) = current {
-                                    current = e.borrow_mut().index_get(run_bubble_returns!(
-                                        idx, constants, variables, aliases
-                                    ))?;
-                                } else {
-                                    return Err(MolangError::BadAccess(
-                                        "[]".to_string(),
-                                        format!("{current:?}"),
-                                    ));
-                                }
-                            }
-                            AccessExpr::Call(args) => {
-                                if let Value::Function(function) = current {
-                                    let mut v_args = Vec::new();
-                                    for arg in args {
-                                        v_args.push(run_bubble_returns!(
-                                            arg, constants, variables, aliases
-                                        ))
-                                    }
-                                    current = (function.f.borrow_mut())(v_args)?
-                                } else {
-                                    return Err(MolangError::BadAccess(
-                                        "()".to_string(),
-                                        format!("{current:?}"),
-                                    ));
-                                }
-                            }
+                let (if_true, if_false) = match right {
+                    Expr::Derived(b) => match b.as_ref() {
+                        Instruction::Colon(left, right) => (left, right),
+                        _ => {
+                            return Err(MolangError::SyntaxError(
+                                "Expected colon to close terenary".to_string(),
+                            ))
                         }
+                    },
+                    _ => {
+                        return Err(MolangError::SyntaxError(
+                            "Expected colon to close terenary".to_string(),
+                        ))
                     }
+                };
 
-                    Ok((current, false))
+                expr = if left == 0.0 { if_false } else { if_true };
+                continue;
+            }
+            Instruction::NullishCoalescing(left, right) => match run_bubble_returns!(left, scope) {
+                Value::Null => {
+                    expr = right;
+                    continue;
                 }
-                Instruction::Assignment(left, right) => {
-                    let accesses: &Vec<AccessExpr>;
-
-                    match left {
-                        Expr::Literal(_) => {
-                            return Err(MolangError::NotAssignable(format!("{left:?}")))
-                        }
-                        Expr::Derived(instruction) => match instruction.as_ref() {
-                            Instruction::Access(a) => {
-                                accesses = a;
-                            }
-                            _ => return Err(MolangError::NotAssignable(format!("{left:?}"))),
-                        },
+                a => Ok((a, false)),
+            },
+            Instruction::Colon(_, _) => {
+                Err(MolangError::SyntaxError("Unexpected colon".to_string()))
+            }
+            Instruction::Not(expr) => {
+                let n = match run_bubble_returns!(expr, scope) {
+                    Value::Number(n) => n,
+                    a => {
+                        return Err(MolangError::TypeError(
+                            "Number".to_string(),
+                            format!("{a:?}"),
+                        ))
                     }
+                };
+                if n == 0.0 {
+                    Ok((Value::Number(1.0), false))
+                } else {
+                    Ok((Value::Number(0.0), false))
+                }
+            }
+            Instruction::Return(expr) => Ok((run_bubble_returns!(expr, scope), true)),
+        };
+    }
+}
 
-                    let mut current: *mut Value = &mut Value::Null;
-
-                    for access in accesses {
-                        match access {
-                            AccessExpr::Name(name) => {
-                                let mut name = name;
-                                if let Value::Null = unsafe { current.as_ref().unwrap() } {
-                                    loop {
-                                        if let Some(long_name) = aliases.get(name) {
-                                            name = long_name;
-                                        }
-                                        if let Some(some_current) = variables.get_mut(name) {
-                                            current = some_current;
-                                            break;
-                                        } else {
-                                            if constants.contains_key(name) {
-                                                return Err(MolangError::NotAssignable(format!(
-                                                    "Constant {name}"
-                                                )));
-                                            } else {
-                                                return Err(MolangError::VariableNotFound(
-                                                    format!("{name}"),
-                                                ));
-                                            }
-                                        }
-                                    }
-                                } else if let Value::Struct(struc) =
-                                    unsafe { current.as_mut().unwrap() }
-                                {
-                                    let l_current = struc.get_mut(name);
-                                    if let Some(l_current) = l_current {
-                                        current = l_current;
-                                    } else {
-                                        struc.insert(name.clone(), Value::Struct(HashMap::new()));
-                                        current = struc.get_mut(name).unwrap();
-                                    }
-                                } else if let Value::External(e) =
-                                    unsafe { current.as_mut().unwrap() }
-                                {
-                                    current = &mut e.borrow_mut().get(name);
-                                } else {
-                                    return Err(MolangError::BadAccess(
-                                        ".".to_string(),
-                                        format!("{current:?}"),
-                                    ));
-                                }
-                            }
-                            AccessExpr::Index(idx) => match unsafe { current.as_ref().unwrap() } {
-                                Value::External(e) => {
-                                    current = &mut e.borrow_mut().index_get(
-                                        run_bubble_returns!(idx, constants, variables, aliases),
-                                    )?;
-                                }
-                                _ => {
-                                    return Err(MolangError::BadAccess(
-                                        "[]".to_string(),
-                                        format!("{current:?}"),
-                                    ))
-                                }
-                            },
-                            AccessExpr::Call(_) => {
-                                return Err(MolangError::NotAssignable(format!("{access:?}")));
-                            }
-                        }
-                    }
+/// Runs arithmetic and equality, following a chain such as `a - b - c`, which
+/// nests to the right, link by link rather than recursing into each.
+fn run_chain(instruction: &Instruction, scope: &mut Scope) -> Result<(Value, bool), MolangError> {
+    let mut links = Vec::new();
+    let mut link = instruction;
+
+    let right = loop {
+        let (left, right) = match link {
+            Instruction::Add(left, right)
+            | Instruction::Subtract(left, right)
+            | Instruction::Multiply(left, right)
+            | Instruction::Divide(left, right)
+            | Instruction::Equality(left, right) => (left, right),
+            _ => unreachable!(),
+        };
+
+        let left = run_bubble_returns!(left, scope);
+        if !matches!(link, Instruction::Equality(_, _)) {
+            number(&left)?;
+        }
+        links.push((link, left));
+
+        match right {
+            Expr::Derived(next)
+                if matches!(
+                    next.as_ref(),
+                    Instruction::Add(_, _)
+                        | Instruction::Subtract(_, _)
+                        | Instruction::Multiply(_, _)
+                        | Instruction::Divide(_, _)
+                        | Instruction::Equality(_, _)
+                ) =>
+            {
+                scope.budget.step()?;
+                link = next.as_ref();
+            }
+            right => break run_bubble_returns!(right, scope),
+        }
+    };
 
-                    unsafe { *current = run_bubble_returns!(right, constants, variables, aliases) };
+    let mut value = right;
+
+    for (link, left) in links.into_iter().rev() {
+        value = match link {
+            Instruction::Equality(_, _) => Value::Number((left == value).into()),
+            _ => {
+                let (left, right) = (number(&left)?, number(&value)?);
+                Value::Number(match link {
+                    Instruction::Add(_, _) => left + right,
+                    Instruction::Subtract(_, _) => left - right,
+                    Instruction::Multiply(_, _) => left * right,
+                    Instruction::Divide(_, _) => left / right,
+                    _ => unreachable!(),
+                })
+            }
+        };
+    }
 
-                    Ok((unsafe { (*current).clone() }, false))
+    Ok((value, false))
+}
+
+fn number(value: &Value) -> Result<Number, MolangError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        a => Err(MolangError::TypeError(
+            "Number".to_string(),
+            format!("{a:?}"),
+        )),
+    }
+}
+
+fn run_access(accesses: &[AccessExpr], scope: &mut Scope) -> Result<(Value, bool), MolangError> {
+    let Some((root, mut rest)) = accesses.split_first() else {
+        return Ok((Value::Null, false));
+    };
+
+    let mut current = match root {
+        AccessExpr::Name(name) => {
+            let name = scope
+                .aliases
+                .get(name.as_str())
+                .map_or(name.as_str(), String::as_str);
+
+            if let Some(constant) = scope.constants.get(name) {
+                constant.clone()
+            } else {
+                // Hand the store every member it can resolve on its own, which
+                // excludes a name being called, as that needs its external.
+                let members: Vec<&str> = rest
+                    .iter()
+                    .zip(rest.iter().skip(1).map(Some).chain([None]))
+                    .map_while(|(access, next)| match (access, next) {
+                        (AccessExpr::Name(_), Some(AccessExpr::Call(_))) => None,
+                        (AccessExpr::Name(member), _) => Some(member.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+
+                rest = &rest[members.len()..];
+
+                let value = scope.variables.get_path(name, &members)?;
+                // A member may have been read from an external.
+                if !members.is_empty() {
+                    scope.budget.limits.check_value(&value)?;
                 }
-                Instruction::Equality(left, right) => Ok((
-                    Value::Number(
-                        (run_bubble_returns!(left, constants, variables, aliases)
-                            == run_bubble_returns!(right, constants, variables, aliases))
-                        .into(),
-                    ),
-                    false,
-                )),
-                Instruction::Conditional(left, right) => {
-                    let left = match run_bubble_returns!(left, constants, variables, aliases) {
-                        Value::Number(n) => n,
-                        a => {
-                            return Err(MolangError::TypeError(
-                                "Number".to_string(),
-                                format!("{a:?}"),
-                            ))
-                        }
-                    };
+                value
+            }
+        }
+        AccessExpr::Slot(slot) => scope
+            .slots
+            .get(*slot)
+            .ok_or_else(|| MolangError::VariableNotFound(format!("#{slot}")))?
+            .clone(),
+        _ => return Err(MolangError::SyntaxError(format!("{root:?}"))),
+    };
 
-                    let (if_true, if_false) = match right {
-                        Expr::Derived(b) => match b.as_ref() {
-                            Instruction::Colon(left, right) => (left, right),
-                            _ => {
-                                return Err(MolangError::SyntaxError(
-                                    "Expected colon to close terenary".to_string(),
-                                ))
-                            }
-                        },
-                        _ => {
-                            return Err(MolangError::SyntaxError(
-                                "Expected colon to close terenary".to_string(),
-                            ))
-                        }
-                    };
+    let mut last_external: Option<(Shared<dyn External>, &Symbol)> = None;
 
-                    if left == 0.0 {
-                        run_expr(if_false, constants, variables, aliases)
-                    } else {
-                        run_expr(if_true, constants, variables, aliases)
-                    }
+    for (i, access) in rest.iter().enumerate() {
+        if let AccessExpr::Call(args) = access {
+            if let Some(ref last_external) = last_external {
+                let mut v_args = Vec::new();
+
+                for arg in args {
+                    v_args.push(run_bubble_returns!(arg, scope));
                 }
-                Instruction::NullishCoalescing(left, right) => {
-                    match run_bubble_returns!(left, constants, variables, aliases) {
-                        Value::Null => Ok((
-                            run_bubble_returns!(right, constants, variables, aliases),
-                            false,
-                        )),
-                        a => Ok((a, false)),
-                    }
+
+                current = lock(&last_external.0).call_function(last_external.1, v_args)?;
+                scope.budget.limits.check_value(&current)?;
+                continue;
+            }
+        }
+
+        last_external = None;
+
+        match access {
+            AccessExpr::Name(name) => {
+                if let Value::Struct(struc) = current {
+                    current = struc.get(name.as_str()).unwrap_or(&Value::Null).clone();
+                } else if let Value::External(e) = current {
+                    // A name being called is never read, so `q.f(1)` runs the
+                    // query once, with its arguments.
+                    current = match rest.get(i + 1) {
+                        Some(AccessExpr::Call(_)) => Value::Null,
+                        _ => lock(&e).try_get(name)?,
+                    };
+                    scope.budget.limits.check_value(&current)?;
+                    last_external = Some((e.clone(), name));
+                } else {
+                    return Err(MolangError::BadAccess(
+                        ".".to_string(),
+                        format!("{current:?}"),
+                    ));
                 }
-                Instruction::Colon(_, _) => {
-                    Err(MolangError::SyntaxError("Unexpected colon".to_string()))
+            }
+            AccessExpr::Slot(_) => return Err(MolangError::SyntaxError(format!("{access:?}"))),
+            AccessExpr::Index(idx) => {
+                if let Value::External(e) = current {
+                    // Evaluate first, as the index may read from the same external.
+                    let index = run_bubble_returns!(idx, scope);
+                    current = lock(&e).index_get(index)?;
+                    scope.budget.limits.check_value(&current)?;
+                } else {
+                    return Err(MolangError::BadAccess(
+                        "[]".to_string(),
+                        format!("{current:?}"),
+                    ));
                 }
-                Instruction::Not(expr) => {
-                    let n = match run_bubble_returns!(expr, constants, variables, aliases) {
-                        Value::Number(n) => n,
-                        a => {
-                            return Err(MolangError::TypeError(
-                                "Number".to_string(),
-                                format!("{a:?}"),
-                            ))
-                        }
-                    };
-                    if n == 0.0 {
-                        Ok((Value::Number(1.0), false))
-                    } else {
-                        Ok((Value::Number(0.0), false))
+            }
+            AccessExpr::Call(args) => {
+                if let Value::Function(function) = current {
+                    let mut v_args = Vec::new();
+                    for arg in args {
+                        v_args.push(run_bubble_returns!(arg, scope))
                     }
+                    current = (lock(&function.f))(v_args)?;
+                    scope.budget.limits.check_value(&current)?;
+                } else {
+                    return Err(MolangError::BadAccess(
+                        "()".to_string(),
+                        format!("{current:?}"),
+                    ));
                 }
-                Instruction::Return(expr) => Ok((
-                    run_bubble_returns!(expr, constants, variables, aliases),
-                    true,
-                )),
             }
         }
     }
+
+    Ok((current, false))
+}
+
+fn run_assignment<'a>(
+    mut left: &'a Expr,
+    mut right: &'a Expr,
+    scope: &mut Scope,
+) -> Result<(Value, bool), MolangError> {
+    // `a = b = c` nests to the right. Each target is found in turn, then all
+    // are assigned once the value is known, the innermost first.
+    let mut targets = Vec::new();
+
+    let value = loop {
+        let accesses = match left {
+            Expr::Derived(instruction) => match instruction.as_ref() {
+                Instruction::Access(a) => a,
+                _ => return Err(MolangError::NotAssignable(format!("{left:?}"))),
+            },
+            Expr::Literal(_) => return Err(MolangError::NotAssignable(format!("{left:?}"))),
+        };
+
+        let (root, accesses) = accesses
+            .split_first()
+            .ok_or_else(|| MolangError::NotAssignable(format!("{left:?}")))?;
+
+        let mut path = Vec::with_capacity(accesses.len());
+
+        for access in accesses {
+            path.push(match access {
+                AccessExpr::Name(name) => PathSegment::Name(name),
+                AccessExpr::Index(idx) => PathSegment::Index(run_bubble_returns!(idx, scope)),
+                AccessExpr::Slot(_) | AccessExpr::Call(_) => {
+                    return Err(MolangError::NotAssignable(format!("{access:?}")))
+                }
+            });
+        }
+
+        targets.push((root, path));
+
+        if let Expr::Derived(next) = right {
+            if let Instruction::Assignment(next_left, next_right) = next.as_ref() {
+                scope.budget.step()?;
+                (left, right) = (next_left, next_right);
+                continue;
+            }
+        }
+
+        break run_bubble_returns!(right, scope);
+    };
+
+    for (root, path) in targets.into_iter().rev() {
+        scope.budget.limits.check_value(&value)?;
+        assign_root(root, &path, value.clone(), scope)?;
+    }
+
+    Ok((value, false))
+}
+
+fn assign_root(
+    root: &AccessExpr,
+    path: &[PathSegment],
+    value: Value,
+    scope: &mut Scope,
+) -> Result<(), MolangError> {
+    match root {
+        AccessExpr::Name(name) => {
+            let name = scope
+                .aliases
+                .get(name.as_str())
+                .map_or(name.as_str(), String::as_str);
+            if scope.constants.contains_key(name) {
+                return Err(MolangError::NotAssignable(format!("Constant {name}")));
+            }
+
+            // Checked before writing, so a rejected member never lands.
+            if scope.budget.limits.max_struct_size.is_some() {
+                let variables = &*scope.variables;
+                scope.budget.limits.check_struct_lens(lens_after(
+                    &names(path),
+                    |path| variables.struct_len(name, path),
+                    |path| variables.has_member(name, path),
+                ))?;
+            }
+
+            scope.variables.set_path(name, path, value)
+        }
+        AccessExpr::Slot(slot) => {
+            let target = scope
+                .slots
+                .get_mut(*slot)
+                .ok_or_else(|| MolangError::VariableNotFound(format!("#{slot}")))?;
+
+            if scope.budget.limits.max_struct_size.is_some() {
+                let target = &*target;
+                scope.budget.limits.check_struct_lens(lens_after(
+                    &names(path),
+                    |path| struct_len(target, path),
+                    |path| member(target, path).is_some(),
+                ))?;
+            }
+
+            assign(target, path, value)
+        }
+        _ => Err(MolangError::NotAssignable(format!("{root:?}"))),
+    }
+}
+
+/// The members an assignment's path names before any index, each of which
+/// the struct before it may have gained.
+fn names<'a>(path: &[PathSegment<'a>]) -> Vec<&'a str> {
+    path.iter()
+        .map_while(|segment| match segment {
+            PathSegment::Name(name) => Some(*name),
+            PathSegment::Index(_) => None,
+        })
+        .collect()
+}
+
+/// The sizes the structs along `names` would have once it is assigned, from
+/// the root down. Missing members are created as structs holding the next.
+fn lens_after(
+    names: &[&str],
+    struct_len: impl Fn(&[&str]) -> Option<usize>,
+    has_member: impl Fn(&[&str]) -> bool,
+) -> Vec<Option<usize>> {
+    (0..names.len())
+        .map(|len| {
+            let added = usize::from(!has_member(&names[..=len]));
+            match struct_len(&names[..len]) {
+                Some(n) => Some(n + added),
+                None if len > 0 && !has_member(&names[..len]) => Some(1),
+                None => None,
+            }
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod test {
-    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+    use std::collections::HashMap;
 
-    use crate::{compile, run, value::Function, MolangError, Value};
+    use crate::{
+        compile, lock, run, shared,
+        value::Function,
+        visitor::test::{Arithmetic, Fold},
+        Block, External, MolangEq, MolangError, Number, Value, Visitor, VisitorMut,
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    const PI: Number = std::f64::consts::PI as Number;
+
+    /// Runs `source` without variables, checking that folding its arithmetic
+    /// with a visitor leaves none behind and gives the same value.
+    fn eval_folded(source: &str) -> Value {
+        let eval = |block: &Block| {
+            run(block, &HashMap::new(), &mut HashMap::new(), &HashMap::new()).unwrap()
+        };
+
+        let block = compile(source).unwrap();
+        let mut folded = block.clone();
+        Fold.visit_block_mut(&mut folded);
+
+        let mut arithmetic = Arithmetic::default();
+        arithmetic.visit_block(&folded);
+        assert_eq!(0, arithmetic.count, "{source}");
+
+        let value = eval(&block);
+        assert_eq!(value, eval(&folded), "{source}");
+        value
+    }
+
+    #[derive(Debug)]
+    struct Probe {
+        value: Value,
+    }
+
+    impl MolangEq for Probe {
+        fn molang_eq(&self, _rhs: &Value) -> bool {
+            false
+        }
+    }
+
+    impl External for Probe {
+        fn get(&mut self, property: &str) -> Value {
+            match property {
+                "value" => self.value.clone(),
+                _ => Value::Null,
+            }
+        }
+
+        fn set(&mut self, property: &str, value: Value) -> Result<(), MolangError> {
+            match property {
+                "value" => {
+                    self.value = value;
+                    Ok(())
+                }
+                _ => Err(MolangError::NotAssignable(property.to_string())),
+            }
+        }
+
+        fn call_function(
+            &mut self,
+            function: &str,
+            _args: Vec<Value>,
+        ) -> Result<Value, MolangError> {
+            Err(MolangError::FunctionNotFound(function.to_string()))
+        }
+
+        fn index_get(&mut self, _index: Value) -> Result<Value, MolangError> {
+            Ok(self.value.clone())
+        }
+
+        fn index_set(&mut self, _index: Value, value: Value) -> Result<(), MolangError> {
+            self.value = value;
+            Ok(())
+        }
+    }
 
     #[test]
     fn function() {
@@ -374,8 +612,8 @@ mod test {
         math.insert(
             "max".into(),
             Value::Function(Function {
-                f: Rc::new(RefCell::new(|args| {
-                    let mut biggest: Option<f32> = None;
+                f: shared(|args| {
+                    let mut biggest: Option<Number> = None;
 
                     for arg in args {
                         if let Value::Number(num) = arg {
@@ -392,7 +630,7 @@ mod test {
                     Ok(Value::Number(biggest.ok_or(MolangError::FunctionError(
                         "No arguments passed to max".into(),
                     ))?))
-                })),
+                }),
             }),
         );
 
@@ -404,13 +642,14 @@ mod test {
                 &compile("math.max(1, 5, 2) * 100").unwrap(),
                 &constants,
                 &mut HashMap::new(),
-                &mut HashMap::new(),
+                &HashMap::new(),
             )
             .unwrap()
         );
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn constant() {
         let mut constants = HashMap::new();
 
@@ -422,24 +661,22 @@ mod test {
                 &compile("pi * 100").unwrap(),
                 &constants,
                 &mut HashMap::new(),
-                &mut HashMap::new(),
+                &HashMap::new(),
             )
             .unwrap()
         );
     }
 
+    #[cfg(feature = "f64")]
+    #[test]
+    fn f64_precision() {
+        // 2^24 + 1 is the first integer an f32 cannot hold.
+        assert_eq!(Value::Number(16_777_217.0), eval_folded("16777216 + 1"));
+    }
+
     #[test]
     fn ternary_not() {
-        assert_eq!(
-            Value::Number(200.0),
-            run(
-                &compile("!1 ? 100 : 200").unwrap(),
-                &HashMap::new(),
-                &mut HashMap::new(),
-                &mut HashMap::new(),
-            )
-            .unwrap()
-        );
+        assert_eq!(Value::Number(200.0), eval_folded("!1 ? 100 : 200"));
     }
 
     #[test]
@@ -467,4 +704,103 @@ mod test {
             .unwrap()
         );
     }
+
+    #[test]
+    fn assign_variable() {
+        let variables = &mut HashMap::new();
+        variables.insert("x".to_string(), Value::Null);
+        let mut aliases = HashMap::new();
+        aliases.insert("y".to_string(), "x".to_string());
+
+        run(
+            &compile("y = 5").unwrap(),
+            &HashMap::new(),
+            variables,
+            &aliases,
+        )
+        .unwrap();
+
+        assert_eq!(Some(&Value::Number(5.0)), variables.get("x"));
+    }
+
+    #[test]
+    fn assign_errors() {
+        let mut constants = HashMap::new();
+        constants.insert("pi".to_string(), Value::Number(PI));
+        let variables = &mut HashMap::new();
+        variables.insert("s".to_string(), Value::Struct(HashMap::new()));
+        variables.insert("n".to_string(), Value::Number(1.0));
+
+        let mut assign = |source| {
+            run(
+                &compile(source).unwrap(),
+                &constants,
+                variables,
+                &HashMap::new(),
+            )
+            .unwrap_err()
+        };
+
+        assert!(matches!(assign("pi = 3"), MolangError::NotAssignable(_)));
+        assert!(matches!(
+            assign("missing = 3"),
+            MolangError::VariableNotFound(_)
+        ));
+        assert!(matches!(assign("s[0] = 3"), MolangError::BadAccess(_, _)));
+        assert!(matches!(assign("n.x = 3"), MolangError::BadAccess(_, _)));
+        assert!(matches!(assign("s.f() = 3"), MolangError::NotAssignable(_)));
+    }
+
+    #[test]
+    fn assign_through_external() {
+        let probe = shared(Probe {
+            value: Value::Number(1.0),
+        });
+        let variables = &mut HashMap::new();
+        variables.insert("e".to_string(), Value::External(probe.clone()));
+
+        let mut run = |source| {
+            run(
+                &compile(source).unwrap(),
+                &HashMap::new(),
+                variables,
+                &HashMap::new(),
+            )
+        };
+
+        run("e.value = 2").unwrap();
+        assert_eq!(Value::Number(2.0), lock(&probe).value);
+
+        run("e[0] = 3").unwrap();
+        assert_eq!(Value::Number(3.0), lock(&probe).value);
+
+        lock(&probe).value = Value::Struct(HashMap::new());
+        run("e.value.nested = 4").unwrap();
+        assert_eq!(Value::Number(4.0), run("e.value.nested").unwrap());
+
+        assert!(matches!(
+            run("e.missing = 5"),
+            Err(MolangError::NotAssignable(_))
+        ));
+    }
+
+    #[test]
+    fn brackets() {
+        for (source, expected) in [("((1))", 1.0), ("(1) + (2)", 3.0), ("((1 + 2)) * (3)", 9.0)] {
+            assert_eq!(Value::Number(expected), eval_folded(source));
+        }
+    }
+
+    #[test]
+    fn associativity() {
+        // Operators sharing a precedence group to the right.
+        for (source, expected) in [
+            ("10 - 2 - 3", 11.0),
+            ("8 / 4 / 2", 4.0),
+            ("2 - 1 + 1", 0.0),
+            ("1 ? 0 : 1 ? 2 : 3", 0.0),
+        ] {
+            assert_eq!(Value::Number(expected), eval_folded(source), "{source}");
+        }
+    }
 }
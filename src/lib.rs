@@ -1,29 +1,133 @@
+#![forbid(unsafe_code)]
+
 mod blockiser;
+mod cancel;
+mod columnar;
+mod cost;
 mod data;
+mod dependencies;
+mod formatter;
 mod interpreter;
+mod limits;
+mod linker;
+mod linter;
+mod minifier;
+mod optimiser;
 mod parser;
-mod state;
+mod printer;
+mod reachability;
+mod runtime;
+mod symbol;
+mod sync;
 mod tokeniser;
+mod types;
 mod value;
+mod variables;
+mod visitor;
 
 use blockiser::blockise;
-use blockiser::Block;
+pub use blockiser::Block;
+pub use cancel::CancelHandle;
+pub use columnar::Columns;
+pub use cost::estimate_cost;
+pub use cost::CostModel;
+pub use dependencies::dependencies;
+pub use dependencies::Dependencies;
+pub use formatter::format_source;
+pub use interpreter::run_linked;
 pub use interpreter::MolangError;
+pub use limits::Limit;
+pub use limits::Limits;
+pub use linker::link;
+pub use linker::LinkError;
+pub use linker::Schema;
+pub use linter::lint;
+pub use linter::Diagnostic;
+pub use linter::LintCode;
+pub use linter::LintConfig;
+pub use linter::Severity;
+pub use minifier::minify;
+pub use minifier::MinifyError;
 pub use molang_proc_macro::MolangStruct;
+pub use optimiser::optimise;
+pub use parser::AccessExpr;
 pub use parser::Expr;
+use parser::ExprSpan;
+pub use parser::Instruction;
+pub use printer::PrintError;
+pub use reachability::unreachable_code;
+pub use reachability::unreachable_spans;
+pub use reachability::Unreachable;
+pub use runtime::Options;
+pub use runtime::QueryProvider;
+pub use runtime::Runtime;
+pub use runtime::RuntimeBuilder;
+pub use symbol::Interner;
+pub use symbol::Symbol;
+pub use sync::lock;
+pub use sync::shared;
+pub use sync::MaybeSend;
+pub use sync::Shared;
 use thiserror::Error;
+pub use tokeniser::lex;
+pub use tokeniser::SourceKind;
+pub use tokeniser::SourceToken;
 use tokeniser::TokeniseError;
+pub use types::check_types;
+pub use types::Signature;
+pub use types::Signatures;
+pub use types::Type;
+pub use types::TypeError;
 pub use value::External;
 pub use value::FromMolangValue;
 pub use value::Function;
 pub use value::MolangEq;
+pub use value::Number;
 pub use value::ToMolangValue;
 pub use value::Value;
+pub use variables::PathSegment;
+pub use variables::VariableStore;
+pub use visitor::{
+    walk_access, walk_access_expr, walk_access_expr_mut, walk_access_mut, walk_block,
+    walk_block_mut, walk_call, walk_call_mut, walk_expr, walk_expr_mut, walk_instruction,
+    walk_instruction_mut, Visitor, VisitorMut,
+};
 
 pub fn compile(expr: &str) -> Result<Block, CompileError> {
-    match tokeniser::tokenise(expr) {
+    compile_with_limits(expr, &Limits::default())
+}
+
+pub fn compile_with_limits(expr: &str, limits: &Limits) -> Result<Block, CompileError> {
+    compile_spanned(expr, limits, None, None)
+}
+
+/// Compiles with identifiers interned into `interner`.
+pub fn compile_interned(
+    expr: &str,
+    limits: &Limits,
+    interner: &Interner,
+) -> Result<Block, CompileError> {
+    compile_spanned(expr, limits, Some(interner), None)
+}
+
+/// Compiles, listing where each expression came from in `spans`.
+pub(crate) fn compile_spanned(
+    expr: &str,
+    limits: &Limits,
+    interner: Option<&Interner>,
+    spans: Option<&mut Vec<ExprSpan>>,
+) -> Result<Block, CompileError> {
+    let max_depth = limits.max_depth.unwrap_or(usize::MAX);
+
+    if tokeniser::nesting_depth(expr) > max_depth {
+        return Err(CompileError::LimitExceeded(Limit::Depth));
+    }
+
+    match tokeniser::tokenise(expr, interner, max_depth) {
         Err(te) => Err(CompileError::TokeniseError(te)),
-        Ok(tokens) => blockise(tokens),
+        // Only `{}` blocks may be empty; a script with nothing in it is incomplete.
+        Ok(tokens) if tokens.is_empty() => Err(CompileError::IncompleteExpression),
+        Ok(tokens) => blockise(tokens, max_depth, spans),
     }
 }
 
@@ -39,4 +143,102 @@ pub enum CompileError {
 
     #[error("Tokenise error {0}")]
     TokeniseError(TokeniseError),
+
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(Limit),
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        compile, estimate_cost, format_source, lint, minify, CostModel, Limit, Limits, LintConfig,
+        MinifyError, MolangError, Runtime, Value,
+    };
+
+    const FRAGMENTS: &[&str] = &[
+        "1", "0.5", "1_0", "\"s\"", "v", "v.x", "q", "q.a", "q.f(", "q[", "q[q.a]", "max(", "(",
+        ")", "[", "]", "{", "}", ",", ";", "+", "-", "*", "/", "?", ":", "??", "=", "==", "!",
+        "return ", " ", ".", "_", "é", "\"", "//", "/*", "*/", "\n",
+    ];
+
+    fn runtime(max_steps: usize, optimise: bool) -> Runtime {
+        Runtime::builder()
+            .variable("v", Value::Struct(HashMap::new()))
+            .function("max", |args| {
+                Ok(args.into_iter().next().unwrap_or(Value::Null))
+            })
+            .query_provider("q", |_: &str, args: Vec<Value>| {
+                Ok(args.into_iter().next().unwrap_or(Value::Number(1.0)))
+            })
+            .limits(Limits {
+                max_steps: Some(max_steps),
+                max_string_length: Some(1024),
+                ..Default::default()
+            })
+            .optimise(optimise)
+            .build()
+    }
+
+    /// Runs every source the fuzz target would, so panics turn up without `cargo fuzz`.
+    fn compile_and_run(source: &str) {
+        let _: Result<Value, MolangError> = runtime(10_000, true).eval_str(source);
+    }
+
+    #[test]
+    fn no_panics() {
+        // xorshift, so failures reproduce
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+
+        for _ in 0..if cfg!(miri) { 100 } else { 20_000 } {
+            let len = next() % 16;
+            let source: String = (0..len)
+                .map(|_| FRAGMENTS[next() % FRAGMENTS.len()])
+                .collect();
+
+            compile_and_run(&source);
+
+            if let Ok(block) = compile(&source) {
+                let max_steps =
+                    estimate_cost(&block, &CostModel::new(), &HashMap::new(), &HashMap::new());
+                assert!(
+                    !matches!(
+                        runtime(max_steps as usize, false).eval_str(&source),
+                        Err(MolangError::LimitExceeded(Limit::Steps))
+                    ),
+                    "{source:?}"
+                );
+            }
+
+            if let Ok(formatted) = format_source(&source) {
+                assert_eq!(
+                    Ok(&formatted),
+                    format_source(&formatted).as_ref(),
+                    "{source:?}"
+                );
+                assert_eq!(
+                    compile(&source).ok(),
+                    compile(&formatted).ok(),
+                    "{source:?}"
+                );
+            }
+
+            let _ = lint(&source, &LintConfig::new());
+
+            for fold in [false, true] {
+                let minified = minify(&source, fold);
+                assert!(
+                    !matches!(minified, Err(MinifyError::Mismatch(_))),
+                    "{source:?}"
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,423 @@
+use std::{fmt::Display, time::Instant};
+
+use crate::{CancelHandle, MolangError, Value};
+
+/// Bounds on the work a script may cause, for running untrusted Molang.
+///
+/// `None` means unlimited. The default only bounds nesting depth, which keeps
+/// compilation and evaluation off the end of the stack.
+///
+/// There is no limit on loop iterations yet, as scripts cannot loop: `loop`
+/// and `for_each` are not part of the grammar. It is deferred until they are,
+/// and until then `max_steps` bounds everything a script does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Deepest nesting of brackets, calls, indexes, prefix operators and
+    /// access chains such as `v.a.b` accepted at compile time. A chain of
+    /// operators such as `a + b + c` is compiled and evaluated link by link
+    /// rather than by recursing, so only counts a level per 64 operators.
+    pub max_depth: Option<usize>,
+    /// Expressions evaluated per run.
+    pub max_steps: Option<usize>,
+    /// Longest string read from an external, returned by a call or assigned.
+    pub max_string_length: Option<usize>,
+    /// Most members in a struct read from an external, returned by a call,
+    /// assigned, or assigned to.
+    pub max_struct_size: Option<usize>,
+}
+
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_steps: None,
+            max_string_length: None,
+            max_struct_size: None,
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_depth: None,
+            max_steps: None,
+            max_string_length: None,
+            max_struct_size: None,
+        }
+    }
+
+    pub(crate) fn check_value(&self, value: &Value) -> Result<(), MolangError> {
+        match value {
+            Value::String(s) if exceeds(self.max_string_length, s.len()) => {
+                Err(MolangError::LimitExceeded(Limit::StringLength))
+            }
+            Value::Struct(s) if exceeds(self.max_struct_size, s.len()) => {
+                Err(MolangError::LimitExceeded(Limit::StructSize))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the structs along an assignment's path, which may each gain a
+    /// member, given the sizes they would have afterwards from the root down.
+    pub(crate) fn check_struct_lens(
+        &self,
+        lens: impl IntoIterator<Item = Option<usize>>,
+    ) -> Result<(), MolangError> {
+        for len in lens.into_iter().flatten() {
+            if exceeds(self.max_struct_size, len) {
+                return Err(MolangError::LimitExceeded(Limit::StructSize));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_steps(&self, steps: usize) -> Result<(), MolangError> {
+        if exceeds(self.max_steps, steps) {
+            Err(MolangError::LimitExceeded(Limit::Steps))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// How often, in steps, evaluation looks at its cancel handle and deadline.
+const INTERRUPT_INTERVAL: usize = 64;
+
+/// The running tally checked against [`Limits`] while evaluating one block.
+pub struct Budget<'a> {
+    pub limits: &'a Limits,
+    pub cancel: Option<&'a CancelHandle>,
+    pub deadline: Option<Instant>,
+    steps: usize,
+}
+
+impl<'a> Budget<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        Self {
+            limits,
+            cancel: None,
+            deadline: None,
+            steps: 0,
+        }
+    }
+
+    pub fn interruptible(self, cancel: &'a CancelHandle, deadline: Option<Instant>) -> Self {
+        Self {
+            cancel: Some(cancel),
+            deadline,
+            ..self
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), MolangError> {
+        self.steps += 1;
+        self.limits.check_steps(self.steps)?;
+
+        if self.steps % INTERRUPT_INTERVAL == 1 {
+            self.check_interrupted()?;
+        }
+
+        Ok(())
+    }
+
+    /// Carries on from `steps` already taken, e.g. by earlier rows of a batch
+    /// evaluated under one budget.
+    pub fn resume(self, steps: usize) -> Self {
+        Self { steps, ..self }
+    }
+
+    pub fn taken(&self) -> usize {
+        self.steps
+    }
+
+    /// Counts `steps` at once, e.g. every operation of a columnar program over
+    /// a chunk of rows, and looks at the cancel handle and deadline.
+    pub fn steps(&mut self, steps: usize) -> Result<(), MolangError> {
+        self.steps = self.steps.saturating_add(steps);
+        self.limits.check_steps(self.steps)?;
+        self.check_interrupted()
+    }
+
+    fn check_interrupted(&self) -> Result<(), MolangError> {
+        if self.cancel.is_some_and(CancelHandle::is_cancelled) {
+            return Err(MolangError::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(MolangError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+fn exceeds(limit: Option<usize>, n: usize) -> bool {
+    limit.is_some_and(|limit| n > limit)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    Steps,
+    StringLength,
+    StructSize,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Limit::Depth => "nesting depth",
+            Limit::Steps => "evaluation steps",
+            Limit::StringLength => "string length",
+            Limit::StructSize => "struct size",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        compile, compile_with_limits, tokeniser::TokeniseError, CompileError, MolangError, Number,
+        Runtime, Value,
+    };
+
+    use super::{Limit, Limits, DEFAULT_MAX_DEPTH};
+
+    fn too_deep(source: &str) -> bool {
+        matches!(
+            compile(source),
+            Err(CompileError::LimitExceeded(Limit::Depth))
+        )
+    }
+
+    #[test]
+    fn depth() {
+        let n = 1_000;
+
+        assert!(too_deep(&format!("{}1{}", "(".repeat(n), ")".repeat(n))));
+        assert!(too_deep(&format!(
+            "{}1{}",
+            "1 + (".repeat(n),
+            ")".repeat(n)
+        )));
+        assert!(too_deep(&format!("{}1", "!".repeat(n))));
+        assert!(too_deep(&format!("{}1{}", "f(".repeat(n), ")".repeat(n))));
+        assert!(too_deep(&format!("v{} = 1", ".a".repeat(10_000))));
+        assert!(too_deep(&format!("{}1", "1 + ".repeat(100_000))));
+    }
+
+    #[test]
+    fn deepest_allowed() {
+        let mut runtime = Runtime::builder()
+            .variable("v", Value::Struct(HashMap::new()))
+            .build();
+
+        // The access takes a level, and each of its parts another.
+        let chain = format!("v{}", ".a".repeat(DEFAULT_MAX_DEPTH - 2));
+        runtime.eval_str(&format!("{chain} = 1")).unwrap();
+        assert_eq!(Value::Number(1.0), runtime.eval_str(&chain).unwrap());
+        assert!(too_deep(&format!("{chain}.a = 1")));
+
+        assert_eq!(
+            Value::Number(DEFAULT_MAX_DEPTH as Number),
+            runtime
+                .eval_str(&format!(
+                    "{}1{}",
+                    "1 + (".repeat(DEFAULT_MAX_DEPTH - 1),
+                    ")".repeat(DEFAULT_MAX_DEPTH - 1)
+                ))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn chains() {
+        let mut runtime = Runtime::builder()
+            .variable("v", Value::Struct(HashMap::new()))
+            .build();
+        let n = 1_000;
+
+        // Not nested, so not limited by depth.
+        assert_eq!(
+            Value::Number(n as Number),
+            runtime
+                .eval_str(&format!("{}1", "1 + ".repeat(n - 1)))
+                .unwrap()
+        );
+        // Grouped to the right, so every other term is subtracted.
+        assert_eq!(
+            Value::Number(0.0),
+            runtime
+                .eval_str(&format!("{}1", "1 - ".repeat(n - 1)))
+                .unwrap()
+        );
+        assert_eq!(
+            Value::Number(1.0),
+            runtime
+                .eval_str(&format!("{}1", "0 ? 0 : ".repeat(n - 1)))
+                .unwrap()
+        );
+        assert_eq!(
+            Value::Number(1.0),
+            runtime
+                .eval_str(&format!("{}1; return v.a;", "v.a = ".repeat(n - 1)))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn blocks_use_configured_depth() {
+        // Blocks cannot be used in expressions yet, but are compiled as they are lexed.
+        let too_deep = |source: &str, max_depth| {
+            let limits = Limits {
+                max_depth: Some(max_depth),
+                ..Default::default()
+            };
+            match compile_with_limits(source, &limits) {
+                Err(CompileError::TokeniseError(TokeniseError::Block(e))) => {
+                    *e == CompileError::LimitExceeded(Limit::Depth)
+                }
+                e => e == Err(CompileError::LimitExceeded(Limit::Depth)),
+            }
+        };
+
+        let sum = |n| format!("{}1{}", "1 + (".repeat(n), ")".repeat(n));
+        for (n, max_depth) in [(8, 4), (200, 300)] {
+            let expected = n >= max_depth;
+            assert_eq!(expected, too_deep(&sum(n), max_depth));
+            assert_eq!(expected, too_deep(&format!("{{{};}}", sum(n)), max_depth));
+        }
+    }
+
+    #[test]
+    fn steps() {
+        let mut runtime = Runtime::builder()
+            .limits(Limits {
+                max_steps: Some(10),
+                ..Default::default()
+            })
+            .build();
+
+        assert!(runtime.eval_str("1 + 1 + 1").is_ok());
+        assert!(matches!(
+            runtime.eval_str("1 + 1 + 1 + 1 + 1 + 1"),
+            Err(MolangError::LimitExceeded(Limit::Steps))
+        ));
+    }
+
+    #[test]
+    fn values() {
+        let mut runtime = Runtime::builder()
+            .function("repeat", |args| match args.as_slice() {
+                [Value::Number(n)] => Ok(Value::String("a".repeat(*n as usize).into())),
+                _ => Err(MolangError::FunctionError("Expected a number".into())),
+            })
+            .query_provider("query", |name: &str, _| Ok(Value::String(name.into())))
+            .variable("variable", Value::Struct(HashMap::new()))
+            .constant(
+                "big",
+                Value::Struct(HashMap::from([
+                    ("a".to_string(), Value::Null),
+                    ("b".to_string(), Value::Null),
+                ])),
+            )
+            .limits(Limits {
+                max_string_length: Some(4),
+                max_struct_size: Some(1),
+                ..Default::default()
+            })
+            .build();
+
+        assert!(runtime.eval_str("repeat(4)").is_ok());
+        assert!(matches!(
+            runtime.eval_str("repeat(5)"),
+            Err(MolangError::LimitExceeded(Limit::StringLength))
+        ));
+        assert!(matches!(
+            runtime.eval_str("variable.x = big"),
+            Err(MolangError::LimitExceeded(Limit::StructSize))
+        ));
+        assert!(runtime.eval_str("query.abcd").is_ok());
+        assert!(matches!(
+            runtime.eval_str("query.abcde"),
+            Err(MolangError::LimitExceeded(Limit::StringLength))
+        ));
+        assert!(matches!(
+            runtime.eval_str("query.abcde == 0"),
+            Err(MolangError::LimitExceeded(Limit::StringLength))
+        ));
+    }
+
+    #[test]
+    fn growing_structs() {
+        let eval = |source| {
+            Runtime::builder()
+                .variable("v", Value::Struct(HashMap::new()))
+                .limits(Limits {
+                    max_struct_size: Some(2),
+                    ..Default::default()
+                })
+                .build()
+                .eval_str(source)
+        };
+
+        assert!(matches!(
+            eval("v.a = 1; v.b = 1; v.c = 1; v.d = 1;"),
+            Err(MolangError::LimitExceeded(Limit::StructSize))
+        ));
+        assert!(matches!(
+            eval("v.s.a = 1; v.s.b = 1; v.s.c = 1;"),
+            Err(MolangError::LimitExceeded(Limit::StructSize))
+        ));
+        // Overwriting a member adds nothing.
+        assert!(eval("v.a = 1; v.b = 1; v.a = 2; v.b = 3;").is_ok());
+    }
+
+    #[test]
+    fn rejected_members_are_not_written() {
+        let mut runtime = Runtime::builder()
+            .variable("variable", Value::Struct(HashMap::new()))
+            .alias("v", "variable")
+            .limits(Limits {
+                max_struct_size: Some(2),
+                ..Default::default()
+            })
+            .build();
+        runtime.eval_str("v.a = 1; v.b = 2;").unwrap();
+        let before = runtime.variables().clone();
+
+        for source in ["v.e = v.a", "v.f.g = 1", "v.e = v.a", "v.h = 1"] {
+            assert!(matches!(
+                runtime.eval_str(source),
+                Err(MolangError::LimitExceeded(Limit::StructSize))
+            ));
+            assert_eq!(before, *runtime.variables());
+        }
+    }
+
+    #[test]
+    fn rejected_members_are_not_written_to_slots() {
+        let mut schema = crate::Schema::new();
+        schema.variable("v");
+        let block = crate::link(compile("v.b = 2").unwrap(), &schema).unwrap();
+        let limits = Limits {
+            max_struct_size: Some(1),
+            ..Default::default()
+        };
+        let v = Value::Struct(HashMap::from([("a".to_string(), Value::Number(1.0))]));
+        let mut slots = [v.clone()];
+
+        assert!(matches!(
+            crate::run_linked(&block, &mut slots, &limits),
+            Err(MolangError::LimitExceeded(Limit::StructSize))
+        ));
+        assert_eq!(v, slots[0]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    blockiser::Block,
+    parser::{AccessExpr, Instruction},
+    visitor::{walk_access_mut, walk_instruction_mut, VisitorMut},
+    Expr,
+};
+
+/// The names a linked block may refer to, each bound to an index into the slot
+/// array passed to [`crate::run_linked`].
+#[derive(Debug, Default, Clone)]
+pub struct Schema {
+    slots: HashMap<String, SlotInfo>,
+    aliases: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SlotInfo {
+    index: usize,
+    assignable: bool,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a read-only name, returning its slot.
+    pub fn constant(&mut self, name: impl Into<String>) -> usize {
+        self.declare(name.into(), false)
+    }
+
+    /// Declares an assignable name, returning its slot.
+    pub fn variable(&mut self, name: impl Into<String>) -> usize {
+        self.declare(name.into(), true)
+    }
+
+    pub fn alias(&mut self, alias: impl Into<String>, name: impl Into<String>) {
+        self.aliases.insert(alias.into(), name.into());
+    }
+
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.info(name).map(|info| info.index)
+    }
+
+    /// The number of slots a linked block needs.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    fn declare(&mut self, name: String, assignable: bool) -> usize {
+        let index = self.slots.len();
+        let info = self
+            .slots
+            .entry(name)
+            .or_insert(SlotInfo { index, assignable });
+        info.assignable = assignable;
+        info.index
+    }
+
+    fn info(&self, name: &str) -> Option<SlotInfo> {
+        let name = self.aliases.get(name).map_or(name, String::as_str);
+        self.slots.get(name).copied()
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LinkError {
+    #[error("Unresolved name: `{0}`")]
+    Unresolved(String),
+
+    #[error("Not assignable: `{0}`")]
+    NotAssignable(String),
+}
+
+/// Resolves every root name in `block` against `schema`, so that evaluation with
+/// [`crate::run_linked`] indexes slots instead of hashing names.
+pub fn link(mut block: Block, schema: &Schema) -> Result<Block, LinkError> {
+    let mut linker = Linker {
+        schema,
+        assigning: false,
+        error: None,
+    };
+    linker.visit_block_mut(&mut block);
+
+    match linker.error {
+        Some(error) => Err(error),
+        None => Ok(block),
+    }
+}
+
+struct Linker<'a> {
+    schema: &'a Schema,
+    /// Whether the access being visited is the target of an assignment.
+    assigning: bool,
+    /// The first name which failed to link.
+    error: Option<LinkError>,
+}
+
+impl Linker<'_> {
+    fn slot(&self, name: &str, assigning: bool) -> Result<usize, LinkError> {
+        let info = self
+            .schema
+            .info(name)
+            .ok_or_else(|| LinkError::Unresolved(name.to_string()))?;
+        if assigning && !info.assignable {
+            return Err(LinkError::NotAssignable(name.to_string()));
+        }
+        Ok(info.index)
+    }
+}
+
+impl VisitorMut for Linker<'_> {
+    fn visit_instruction_mut(&mut self, instruction: &mut Instruction) {
+        let Instruction::Assignment(left, right) = instruction else {
+            return walk_instruction_mut(self, instruction);
+        };
+
+        self.assigning = matches!(
+            left,
+            Expr::Derived(i) if matches!(i.as_ref(), Instruction::Access(_))
+        );
+        self.visit_expr_mut(left);
+        self.visit_expr_mut(right);
+    }
+
+    fn visit_access_mut(&mut self, accesses: &mut Vec<AccessExpr>) {
+        // Only the root is assigned through; indexes and arguments are read.
+        let assigning = std::mem::take(&mut self.assigning);
+
+        if let Some(AccessExpr::Name(name)) = accesses.first() {
+            match self.slot(name, assigning) {
+                Ok(slot) => accesses[0] = AccessExpr::Slot(slot),
+                Err(error) => {
+                    self.error.get_or_insert(error);
+                }
+            }
+        }
+
+        walk_access_mut(self, accesses);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{compile, run_linked, Limits, Value};
+
+    use super::{link, LinkError, Schema};
+
+    #[test]
+    fn slots() {
+        let mut schema = Schema::new();
+        let pi = schema.constant("pi");
+        let variable = schema.variable("variable");
+        schema.alias("v", "variable");
+
+        let mut slots = vec![Value::Null; schema.len()];
+        slots[pi] = Value::Number(3.0);
+        slots[variable] = Value::Struct(HashMap::new());
+
+        let block = link(compile("v.x = pi * 2; return v.x + 1;").unwrap(), &schema).unwrap();
+
+        assert_eq!(
+            Value::Number(7.0),
+            run_linked(&block, &mut slots, &Limits::default()).unwrap()
+        );
+        assert_eq!(
+            Value::Number(6.0),
+            run_linked(
+                &link(compile("variable.x").unwrap(), &schema).unwrap(),
+                &mut slots,
+                &Limits::default()
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn unresolved() {
+        let mut schema = Schema::new();
+        schema.variable("a");
+
+        assert_eq!(
+            Some(LinkError::Unresolved("b".to_string())),
+            link(compile("a + b").unwrap(), &schema).err()
+        );
+    }
+
+    #[test]
+    fn constant_not_assignable() {
+        let mut schema = Schema::new();
+        schema.constant("pi");
+
+        assert_eq!(
+            Some(LinkError::NotAssignable("pi".to_string())),
+            link(compile("pi.x = 1").unwrap(), &schema).err()
+        );
+
+        // Names read while assigning need not be assignable.
+        schema.variable("v");
+        assert!(link(compile("v[pi] = pi").unwrap(), &schema).is_ok());
+        assert_eq!(
+            Some(LinkError::NotAssignable("pi".to_string())),
+            link(compile("v = pi = 1").unwrap(), &schema).err()
+        );
+    }
+}
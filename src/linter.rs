@@ -0,0 +1,573 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+    ops::Range,
+};
+
+use crate::{
+    compile_spanned, dependencies,
+    parser::{AccessExpr, ExprSpan, Instruction},
+    reachability::{find, Unreachable},
+    tokeniser::{lex, statement_spans},
+    visitor::{walk_expr, walk_instruction, Visitor},
+    CompileError, Expr, Limits, Value,
+};
+
+/// A lint, identified by a code which never changes once published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintCode {
+    /// `(v.a = 1) ? ...`, or `v.a = 1 ? ...` where `==` was likely meant.
+    AssignmentInCondition,
+    /// A statement after a `return` which always runs.
+    UnreachableAfterReturn,
+    /// A `temp` variable which is assigned but never read.
+    UnusedTemp,
+    /// `==` on a fractional number, which rounding rarely lets match.
+    FloatEquality,
+    /// A query missing from [`LintConfig::queries`].
+    UnknownQuery,
+    /// A query marked with [`LintConfig::deprecated`].
+    DeprecatedQuery,
+    /// A ternary branch which its constant condition never takes.
+    UnreachableBranch,
+}
+
+impl LintCode {
+    pub const ALL: [LintCode; 7] = [
+        LintCode::AssignmentInCondition,
+        LintCode::UnreachableAfterReturn,
+        LintCode::UnusedTemp,
+        LintCode::FloatEquality,
+        LintCode::UnknownQuery,
+        LintCode::DeprecatedQuery,
+        LintCode::UnreachableBranch,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LintCode::AssignmentInCondition => "M001",
+            LintCode::UnreachableAfterReturn => "M002",
+            LintCode::UnusedTemp => "M003",
+            LintCode::FloatEquality => "M004",
+            LintCode::UnknownQuery => "M005",
+            LintCode::DeprecatedQuery => "M006",
+            LintCode::UnreachableBranch => "M007",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintCode::AssignmentInCondition => "assignment-in-condition",
+            LintCode::UnreachableAfterReturn => "unreachable-after-return",
+            LintCode::UnusedTemp => "unused-temp",
+            LintCode::FloatEquality => "float-equality",
+            LintCode::UnknownQuery => "unknown-query",
+            LintCode::DeprecatedQuery => "deprecated-query",
+            LintCode::UnreachableBranch => "unreachable-branch",
+        }
+    }
+
+    /// Finds a lint by its code or name, as written in project configuration.
+    pub fn from_name(name: &str) -> Option<LintCode> {
+        LintCode::ALL
+            .into_iter()
+            .find(|lint| lint.code() == name || lint.name() == name)
+    }
+
+    fn default_severity(&self) -> Severity {
+        match self {
+            LintCode::UnknownQuery => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Not reported.
+    Allow,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: LintCode,
+    pub severity: Severity,
+    pub message: String,
+    /// Byte range in the linted source.
+    pub span: Range<usize>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            _ => "warning",
+        };
+        write!(
+            f,
+            "{severity}[{}]: {} at {}..{}",
+            self.code.code(),
+            self.message,
+            self.span.start,
+            self.span.end
+        )
+    }
+}
+
+/// Which lints run and how severe they are, with what the project knows about
+/// its queries. Bedrock's `q`, `v`, `t` and `c` aliases are set by default.
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    severities: HashMap<LintCode, Severity>,
+    queries: Option<HashSet<String>>,
+    deprecated: HashMap<String, Option<String>>,
+    aliases: HashMap<String, String>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            severities: HashMap::new(),
+            queries: None,
+            deprecated: HashMap::new(),
+            aliases: [
+                ("q", "query"),
+                ("v", "variable"),
+                ("t", "temp"),
+                ("c", "context"),
+            ]
+            .into_iter()
+            .map(|(alias, name)| (alias.to_string(), name.to_string()))
+            .collect(),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn severity(mut self, code: LintCode, severity: Severity) -> Self {
+        self.severities.insert(code, severity);
+        self
+    }
+
+    /// The catalogue of query names, without `query.`, which enables
+    /// [`LintCode::UnknownQuery`].
+    pub fn queries<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.queries = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Marks a query, without `query.`, as deprecated, optionally naming its
+    /// replacement.
+    pub fn deprecated(mut self, name: &str, replacement: Option<&str>) -> Self {
+        self.deprecated
+            .insert(name.to_string(), replacement.map(str::to_string));
+        self
+    }
+
+    pub fn alias(mut self, alias: &str, name: &str) -> Self {
+        self.aliases.insert(alias.to_string(), name.to_string());
+        self
+    }
+
+    fn severity_of(&self, code: LintCode) -> Severity {
+        self.severities
+            .get(&code)
+            .copied()
+            .unwrap_or_else(|| code.default_severity())
+    }
+}
+
+/// Compiles `source` and reports every enabled lint, in source order.
+pub fn lint(source: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, CompileError> {
+    let mut exprs = Vec::new();
+    let block = compile_spanned(source, &Limits::default(), None, Some(&mut exprs))?;
+    let tokens = lex(source).map_err(CompileError::TokeniseError)?;
+
+    let mut spans = statement_spans(&tokens);
+    if spans.len() != block.statements.len() {
+        spans = vec![0..source.len(); block.statements.len()];
+    }
+
+    let mut linter = Linter {
+        config,
+        exprs: &exprs,
+        next: 0,
+        span: 0..source.len(),
+        conditions: 0,
+        targets: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+
+    for (expr, span) in block.statements.iter().zip(&spans) {
+        linter.span = span.clone();
+        linter.visit_expr(expr);
+    }
+
+    for found in find(&block, &HashMap::new(), &config.aliases) {
+        let (statement, code, message) = match found.unreachable {
+            Unreachable::Statement(statement) => (
+                statement,
+                LintCode::UnreachableAfterReturn,
+                "Statement after `return` never runs",
+            ),
+            Unreachable::Branch {
+                statement, if_true, ..
+            } => (
+                statement,
+                LintCode::UnreachableBranch,
+                if if_true {
+                    "Condition is always false, so this branch never runs"
+                } else {
+                    "Condition is always true, so this branch never runs"
+                },
+            ),
+        };
+        linter.span = spans[statement].clone();
+        let at = found.span(&exprs, &spans);
+        linter.report(code, message.to_string(), at);
+    }
+
+    let deps = dependencies(&block, &config.aliases);
+    for write in &deps.writes {
+        let Some(name) = write.strip_prefix("temp.") else {
+            continue;
+        };
+        if deps.reads.iter().any(|read| overlaps(read, write)) {
+            continue;
+        }
+
+        linter.span = 0..source.len();
+        let at = linter
+            .targets
+            .iter()
+            .find(|(path, _)| path == write)
+            .map(|(_, span)| span.clone());
+        linter.report(
+            LintCode::UnusedTemp,
+            format!("`temp.{name}` is assigned but never read"),
+            at,
+        );
+    }
+
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|d| (d.span.start, d.span.end));
+    Ok(diagnostics)
+}
+
+/// Whether one path is the other or contains it.
+fn overlaps(a: &str, b: &str) -> bool {
+    let contains = |outer: &str, inner: &str| {
+        inner
+            .strip_prefix(outer)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    };
+    contains(a, b) || contains(b, a)
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    /// Where each expression was parsed from, in the order they are visited.
+    exprs: &'a [ExprSpan],
+    /// The index in `exprs` of the next expression visited.
+    next: usize,
+    /// The span of that statement.
+    span: Range<usize>,
+    /// How many conditions the expression being visited is inside.
+    conditions: usize,
+    /// Each path of names assigned to, with its root resolved, and its span.
+    targets: Vec<(String, Range<usize>)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    /// Reports at `at`, or the whole statement if it could not be narrowed.
+    fn report(&mut self, code: LintCode, message: String, at: Option<Range<usize>>) {
+        let severity = self.config.severity_of(code);
+        if severity == Severity::Allow {
+            return;
+        }
+
+        let span = at.unwrap_or_else(|| self.span.clone());
+        let diagnostic = Diagnostic {
+            code,
+            severity,
+            message,
+            span,
+        };
+        if !self.diagnostics.contains(&diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Where the next expression visited was parsed from.
+    fn next_span(&mut self) -> Option<ExprSpan> {
+        let span = self.exprs.get(self.next).cloned();
+        self.next += 1;
+        span
+    }
+
+    /// The span of the leading names of an access, with the path they spell.
+    fn names(
+        &self,
+        accesses: &[AccessExpr],
+        span: &ExprSpan,
+    ) -> Option<(Vec<String>, Range<usize>)> {
+        let names: Vec<String> = accesses
+            .iter()
+            .map_while(|access| match access {
+                AccessExpr::Name(name) => Some(name.to_string()),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(i, name)| match (i, self.config.aliases.get(&name)) {
+                (0, Some(resolved)) => resolved.clone(),
+                _ => name,
+            })
+            .collect();
+
+        let last = span.accesses.get(names.len().checked_sub(1)?)?;
+        Some((names, span.expr.start..last.end))
+    }
+
+    fn lint_instruction(&mut self, instruction: &Instruction, span: &ExprSpan) {
+        match instruction {
+            Instruction::Assignment(left, right) => {
+                if self.conditions > 0 {
+                    self.report(
+                        LintCode::AssignmentInCondition,
+                        "Condition assigns a variable".to_string(),
+                        Some(span.operator.clone()),
+                    );
+                }
+
+                if let Expr::Derived(right) = right {
+                    if let Instruction::Conditional(Expr::Literal(_), _) = right.as_ref() {
+                        self.report(
+                            LintCode::AssignmentInCondition,
+                            "Assigns a conditional on a constant; did you mean `==`?".to_string(),
+                            Some(span.operator.clone()),
+                        );
+                    }
+                }
+
+                // The target is the expression visited next.
+                if let (Expr::Derived(left), Some(target)) = (left, self.exprs.get(self.next)) {
+                    if let Instruction::Access(accesses) = left.as_ref() {
+                        if let Some((names, at)) = self.names(accesses, target) {
+                            self.targets.push((names.join("."), at));
+                        }
+                    }
+                }
+            }
+            Instruction::Equality(left, right) if is_fractional(left) || is_fractional(right) => {
+                self.report(
+                    LintCode::FloatEquality,
+                    "`==` on a fractional number; compare the difference to a tolerance"
+                        .to_string(),
+                    Some(span.operator.clone()),
+                );
+            }
+            Instruction::Access(accesses) => {
+                let Some((names, _)) = self.names(accesses, span) else {
+                    return;
+                };
+                let [root, name, ..] = names.as_slice() else {
+                    return;
+                };
+                if root != "query" {
+                    return;
+                }
+                let at = span.accesses.get(1).map(|name| span.expr.start..name.end);
+
+                if let Some(queries) = &self.config.queries {
+                    if !queries.contains(name.as_str()) {
+                        self.report(
+                            LintCode::UnknownQuery,
+                            format!("Unknown query `query.{name}`"),
+                            at.clone(),
+                        );
+                    }
+                }
+
+                if let Some(replacement) = self.config.deprecated.get(name.as_str()) {
+                    let message = match replacement {
+                        Some(replacement) => {
+                            format!("`query.{name}` is deprecated; use `query.{replacement}`")
+                        }
+                        None => format!("`query.{name}` is deprecated"),
+                    };
+                    self.report(LintCode::DeprecatedQuery, message, at);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        let span = self.next_span();
+        let Expr::Derived(instruction) = expr else {
+            return walk_expr(self, expr);
+        };
+        let Some(span) = span else {
+            return walk_instruction(self, instruction);
+        };
+
+        self.lint_instruction(instruction, &span);
+
+        let Instruction::Conditional(condition, branches) = instruction.as_ref() else {
+            return walk_instruction(self, instruction);
+        };
+
+        self.conditions += 1;
+        self.visit_expr(condition);
+        self.conditions -= 1;
+
+        self.visit_expr(branches);
+    }
+}
+
+/// A literal with a fraction, or a division, which is rarely exact.
+fn is_fractional(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Value::Number(n)) => n.fract() != 0.0,
+        Expr::Derived(i) => matches!(**i, Instruction::Divide(_, _)),
+        Expr::Literal(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use super::{lint, Diagnostic, LintCode, LintConfig, Severity};
+
+    fn codes(source: &str, config: &LintConfig) -> Vec<(&'static str, String)> {
+        lint(source, config)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.code.code(), source[d.span].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn lints() {
+        let config = LintConfig::new()
+            .queries(["anim_time", "is_moving", "life_time"])
+            .deprecated("life_time", Some("anim_time"));
+
+        assert_eq!(
+            vec![
+                ("M001", "=".to_string()),
+                ("M007", "3".to_string()),
+                ("M004", "==".to_string()),
+                ("M005", "q.speed".to_string()),
+                ("M003", "t.unused".to_string()),
+                ("M006", "query.life_time".to_string()),
+                ("M002", "v.c = 3;".to_string()),
+            ],
+            codes(
+                "v.a = 1 ? 2 : 3; v.b = q.anim_time == 0.5; t.x = q.speed; t.unused = t.x;\n\
+                 return query.life_time; v.c = 3;",
+                &config
+            )
+        );
+
+        assert_eq!(
+            vec![("M001", "=".to_string())],
+            codes("(v.a = q.is_moving) ? 1 : 0", &config)
+        );
+        assert!(codes("t.a = q.anim_time / 2; return t.a.x == 1;", &config).is_empty());
+    }
+
+    #[test]
+    fn unreachable() {
+        let config = LintConfig::new();
+
+        assert_eq!(
+            vec![
+                ("M007", "v.b".to_string()),
+                ("M007", "f(2, v.c ? 3 : 4)".to_string()),
+                ("M002", "v.d = 1;".to_string()),
+            ],
+            codes(
+                "v.a = f(0 ? v.b : 1, !q.a ? 1 : 2); (1 == 1) ? (return 1) : f(2, v.c ? 3 : 4);\n\
+                 v.d = 1;",
+                &config
+            )
+        );
+    }
+
+    #[test]
+    fn ternaries_without_colons() {
+        assert_eq!(
+            vec![("M007", "3".to_string())],
+            codes(
+                "v.x = (q.a ? 1) + (1 ? 2 : 3) + (q.c ? 4 : 5)",
+                &LintConfig::new()
+            )
+        );
+    }
+
+    #[test]
+    fn spans_from_tree() {
+        let spans = |source: &str| -> Vec<(&'static str, Range<usize>)> {
+            lint(source, &LintConfig::new().queries(["a"]))
+                .unwrap()
+                .into_iter()
+                .map(|d| (d.code.code(), d.span))
+                .collect()
+        };
+
+        assert_eq!(vec![("M004", 16..18)], spans("v.a == 1 ? (v.b == 0.5) : 0"));
+        assert_eq!(
+            vec![("M001", 5..6), ("M001", 18..19)],
+            spans("(v.a = 1) == (v.b = 2) ? 1 : 0")
+        );
+        assert_eq!(
+            vec![("M005", 0..7), ("M005", 10..17)],
+            spans("q.speed + q.speed")
+        );
+        assert_eq!(
+            vec![("M007", 21..22), ("M007", 31..32)],
+            spans("v.a = q.a ? (1 ? 2 : 3) : (0 ? 4 : 5)")
+        );
+        assert_eq!(vec![("M003", 7..10)], spans("t.u(); t.u = 1;"));
+    }
+
+    #[test]
+    fn config() {
+        let config = LintConfig::new()
+            .severity(LintCode::UnreachableAfterReturn, Severity::Error)
+            .severity(LintCode::FloatEquality, Severity::Allow);
+
+        let diagnostics = lint("return 1 / 3 == 0.3; 2;", &config).unwrap();
+        assert_eq!(
+            vec![Diagnostic {
+                code: LintCode::UnreachableAfterReturn,
+                severity: Severity::Error,
+                message: "Statement after `return` never runs".to_string(),
+                span: 21..23,
+            }],
+            diagnostics
+        );
+        assert_eq!(
+            "error[M002]: Statement after `return` never runs at 21..23",
+            diagnostics[0].to_string()
+        );
+
+        assert_eq!(Some(LintCode::UnusedTemp), LintCode::from_name("M003"));
+        assert_eq!(
+            Some(LintCode::UnusedTemp),
+            LintCode::from_name("unused-temp")
+        );
+        assert!(lint("1 +", &config).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{compile, optimise, optimiser::resolve_aliases, Block, CompileError};
+
+/// The short forms Bedrock defines for the variable namespaces.
+const SHORT_NAMES: [(&str, &str); 3] = [("query", "q"), ("variable", "v"), ("temp", "t")];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MinifyError {
+    #[error("{0}")]
+    Compile(#[from] CompileError),
+
+    #[error("Minified script does not compile to the original: `{0}`")]
+    Mismatch(String),
+}
+
+/// Writes `source` in as few characters as the grammar allows: no optional
+/// spaces or brackets, and `query.`, `variable.` and `temp.` shortened to
+/// `q.`, `v.` and `t.`, so it must run where those aliases exist, as in
+/// Bedrock. With `fold`, arithmetic on literals is evaluated and dead branches
+/// dropped as by [`optimise`], unless that comes out longer, as `0 - 2` does
+/// once folded to a negative number.
+///
+/// The result is compiled again and checked against the original before it
+/// is returned. That check covers printing and name shortening only: folded
+/// output is compared with the original folded the same way, so folding
+/// itself is trusted rather than checked.
+pub fn minify(source: &str, fold: bool) -> Result<String, MinifyError> {
+    let block = compile(source)?;
+    let unfolded = shortest(block.clone(), false)?;
+
+    if !fold {
+        return Ok(unfolded);
+    }
+
+    let folded = shortest(block, true)?;
+    Ok(if folded.len() <= unfolded.len() {
+        folded
+    } else {
+        unfolded
+    })
+}
+
+/// Prints `block` compactly with short names, checking that it reparses to
+/// the same block.
+fn shortest(block: Block, fold: bool) -> Result<String, MinifyError> {
+    let fold = |block: Block| {
+        if fold {
+            optimise(block, &HashMap::new(), &HashMap::new())
+        } else {
+            block
+        }
+    };
+
+    let shorten = SHORT_NAMES
+        .iter()
+        .map(|(long, short)| (long.to_string(), short.to_string()))
+        .collect();
+    let lengthen = SHORT_NAMES
+        .iter()
+        .map(|(long, short)| (short.to_string(), long.to_string()))
+        .collect();
+
+    let block = fold(block);
+    let minified = format!("{:#}", resolve_aliases(block.clone(), &shorten));
+
+    // Compared as text, which the printer keeps one to one with blocks, so a
+    // folded `0 / 0` still matches itself. Both sides are folded, so this
+    // checks the printer, not the optimiser.
+    let expected = format!("{:#}", resolve_aliases(block, &lengthen));
+    let reparsed = compile(&minified).map(|block| resolve_aliases(fold(block), &lengthen));
+    match reparsed {
+        Ok(reparsed) if format!("{reparsed:#}") == expected => Ok(minified),
+        _ => Err(MinifyError::Mismatch(minified)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{minify, MinifyError};
+
+    #[test]
+    fn minify_source() {
+        assert_eq!(
+            "t.a=q.anim_time*(v.speed+1);return t.a;",
+            minify(
+                "temp.a = (query.anim_time) * (variable.speed + 1);\nreturn (t.a);",
+                false
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            "math.sin(q.x,\"a b\")",
+            minify("math.sin( query.x , \"a b\" )", false).unwrap()
+        );
+    }
+
+    #[test]
+    fn fold() {
+        assert_eq!("v.x*2", minify("variable.x * (1 + 1)", true).unwrap());
+        assert_eq!("v.x*(1+1)", minify("variable.x * (1 + 1)", false).unwrap());
+        assert_eq!("return 1;", minify("return 1; v.x = 2;", true).unwrap());
+    }
+
+    #[test]
+    fn fold_never_lengthens() {
+        for (source, expected) in [
+            ("return 0-2", "return 0-2"),
+            ("return 1 - 3", "return 1-3"),
+            ("return 0/0", "return 0/0"),
+            ("return 1 / 0", "return 1/0"),
+            ("v.x * (10 - 20)", "v.x*(0-10)"),
+            ("v.x * (20 - 10)", "v.x*10"),
+        ] {
+            let unfolded = minify(source, false).unwrap();
+            let folded = minify(source, true).unwrap();
+
+            assert_eq!(expected, folded);
+            assert!(folded.len() <= unfolded.len(), "{source}");
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(minify("1 +", false), Err(MinifyError::Compile(_))));
+    }
+}
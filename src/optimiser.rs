@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use crate::{
+    blockiser::Block,
+    parser::{AccessExpr, Instruction},
+    visitor::{walk_access_mut, VisitorMut},
+    Expr, Value,
+};
+
+/// Folds arithmetic on literals and constants, prunes ternaries with constant
+/// conditions and drops statements after an unconditional `return`.
+///
+/// The result is only equivalent when run with the same `constants` and `aliases`.
+pub fn optimise(
+    block: Block,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Block {
+    let mut statements = Vec::new();
+
+    for statement in block.statements {
+        let statement = optimise_expr(statement, constants, aliases);
+        let returns =
+            matches!(&statement, Expr::Derived(i) if matches!(**i, Instruction::Return(_)));
+
+        statements.push(statement);
+
+        if returns {
+            break;
+        }
+    }
+
+    Block {
+        multiple: block.multiple,
+        statements,
+    }
+}
+
+pub(crate) fn optimise_expr(
+    expr: Expr,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Expr {
+    let instruction = match expr {
+        Expr::Literal(_) => return expr,
+        Expr::Derived(i) => *i,
+    };
+
+    fold(match instruction {
+        Instruction::Not(expr) => Instruction::Not(optimise_expr(expr, constants, aliases)),
+        Instruction::Return(expr) => Instruction::Return(optimise_expr(expr, constants, aliases)),
+        Instruction::Assignment(left, right) => {
+            Instruction::Assignment(left, optimise_expr(right, constants, aliases))
+        }
+        Instruction::Access(accesses) => {
+            if let Some(value) = resolve_constant(&accesses, constants, aliases) {
+                return Expr::Literal(value);
+            }
+
+            Instruction::Access(
+                accesses
+                    .into_iter()
+                    .map(|access| match access {
+                        AccessExpr::Name(_) | AccessExpr::Slot(_) => access,
+                        AccessExpr::Index(idx) => {
+                            AccessExpr::Index(optimise_expr(idx, constants, aliases))
+                        }
+                        AccessExpr::Call(args) => AccessExpr::Call(
+                            args.into_iter()
+                                .map(|arg| optimise_expr(arg, constants, aliases))
+                                .collect(),
+                        ),
+                    })
+                    .collect(),
+            )
+        }
+        binary => return optimise_chain(binary, constants, aliases),
+    })
+}
+
+/// Optimises a binary instruction, following a chain such as `a - b - c`,
+/// which nests to the right, link by link rather than recursing into each.
+fn optimise_chain(
+    mut instruction: Instruction,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Expr {
+    let mut links = Vec::new();
+
+    let right = loop {
+        let Ok((link, left, right)) = instruction.into_binary() else {
+            unreachable!()
+        };
+        links.push((link, optimise_expr(left, constants, aliases)));
+
+        // Only the right of an assignment is optimised, so it ends the chain.
+        match right {
+            Expr::Derived(next)
+                if next.is_binary() && !matches!(*next, Instruction::Assignment(_, _)) =>
+            {
+                instruction = *next;
+            }
+            right => break optimise_expr(right, constants, aliases),
+        }
+    };
+
+    links
+        .into_iter()
+        .rev()
+        .fold(right, |right, (link, left)| fold(link(left, right)))
+}
+
+/// Rewrites root names which are aliases to the names they stand for, so the
+/// result runs the same without `aliases`.
+pub(crate) fn resolve_aliases(mut block: Block, aliases: &HashMap<String, String>) -> Block {
+    ResolveAliases { aliases }.visit_block_mut(&mut block);
+    block
+}
+
+struct ResolveAliases<'a> {
+    aliases: &'a HashMap<String, String>,
+}
+
+impl VisitorMut for ResolveAliases<'_> {
+    fn visit_access_mut(&mut self, accesses: &mut Vec<AccessExpr>) {
+        if let Some(AccessExpr::Name(name)) = accesses.first_mut() {
+            if let Some(target) = self.aliases.get(name.as_str()) {
+                *name = target.as_str().into();
+            }
+        }
+        walk_access_mut(self, accesses);
+    }
+}
+
+/// Folds `instruction` if its already optimised operands allow it.
+fn fold(instruction: Instruction) -> Expr {
+    use Expr::Literal;
+    use Value::Number;
+
+    match instruction {
+        Instruction::Add(Literal(Number(l)), Literal(Number(r))) => Literal(Number(l + r)),
+        Instruction::Subtract(Literal(Number(l)), Literal(Number(r))) => Literal(Number(l - r)),
+        Instruction::Multiply(Literal(Number(l)), Literal(Number(r))) => Literal(Number(l * r)),
+        Instruction::Divide(Literal(Number(l)), Literal(Number(r))) => Literal(Number(l / r)),
+        Instruction::Not(Literal(Number(n))) => Literal(Number(if n == 0.0 { 1.0 } else { 0.0 })),
+        Instruction::Equality(Literal(l), Literal(r)) if is_plain(&l) && is_plain(&r) => {
+            Literal(Number((l == r).into()))
+        }
+        Instruction::Conditional(Literal(Number(n)), Expr::Derived(branches))
+            if matches!(*branches, Instruction::Colon(_, _)) =>
+        {
+            let Instruction::Colon(if_true, if_false) = *branches else {
+                unreachable!()
+            };
+            if n == 0.0 {
+                if_false
+            } else {
+                if_true
+            }
+        }
+        Instruction::NullishCoalescing(Literal(Value::Null), right) => right,
+        Instruction::NullishCoalescing(Literal(left), _) => Literal(left),
+        instruction => Expr::Derived(Box::new(instruction)),
+    }
+}
+
+/// Values which are safe to duplicate into the AST and compare at compile time.
+fn is_plain(value: &Value) -> bool {
+    matches!(value, Value::Number(_) | Value::String(_) | Value::Null)
+}
+
+fn resolve_constant(
+    accesses: &[AccessExpr],
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Option<Value> {
+    let mut names = accesses.iter().map(|access| match access {
+        AccessExpr::Name(name) => Some(name),
+        _ => None,
+    });
+
+    let root = names.next()??;
+    let root = aliases
+        .get(root.as_str())
+        .map_or(root.as_str(), String::as_str);
+
+    let mut current = constants.get(root)?;
+
+    for name in names {
+        current = match current {
+            Value::Struct(struc) => struc.get(name?.as_str())?,
+            _ => return None,
+        };
+    }
+
+    is_plain(current).then(|| current.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        blockiser::Block,
+        compile,
+        parser::{AccessExpr, Instruction},
+        Expr, Value,
+    };
+
+    use super::optimise;
+
+    fn optimised(source: &str, constants: &HashMap<String, Value>) -> Block {
+        optimise(compile(source).unwrap(), constants, &HashMap::new())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            vec![Expr::Literal(Value::Number(0.5))],
+            optimised("1 * 0.5 + 0", &HashMap::new()).statements
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let mut constants = HashMap::new();
+        let mut math = HashMap::new();
+        math.insert("two".to_string(), Value::Number(2.0));
+        constants.insert("math".to_string(), Value::Struct(math));
+
+        assert_eq!(
+            vec![Expr::Literal(Value::Number(6.0))],
+            optimised("math.two * 3", &constants).statements
+        );
+
+        // A missing member is left to fail at run time.
+        let source = "math.missing + 1";
+        assert_eq!(compile(source).unwrap(), optimised(source, &constants));
+    }
+
+    #[test]
+    fn ternary() {
+        assert_eq!(
+            vec![Expr::Derived(Box::new(Instruction::Access(vec![
+                AccessExpr::Name("a".into())
+            ])))],
+            optimised("!0 ? a : b", &HashMap::new()).statements
+        );
+    }
+
+    #[test]
+    fn variables_untouched() {
+        assert_eq!(
+            vec![Expr::Derived(Box::new(Instruction::Multiply(
+                Expr::Derived(Box::new(Instruction::Access(vec![AccessExpr::Name(
+                    "a".into()
+                )]))),
+                Expr::Literal(Value::Number(2.0))
+            )))],
+            optimised("a * (1 + 1)", &HashMap::new()).statements
+        );
+    }
+
+    #[test]
+    fn after_return() {
+        assert_eq!(
+            Block {
+                multiple: true,
+                statements: vec![
+                    Expr::Literal(Value::Number(1.0)),
+                    Expr::Derived(Box::new(Instruction::Return(Expr::Literal(Value::Number(
+                        2.0
+                    )))))
+                ]
+            },
+            optimised("1; return 1 + 1; a = 3;", &HashMap::new())
+        );
+    }
+}
@@ -1,16 +1,19 @@
+use std::ops::Range;
+
 use crate::{
     data::Operator,
-    tokeniser::{Access, Token},
-    CompileError, Value,
+    limits::Limit,
+    tokeniser::{Access, Spanned, Token},
+    CompileError, Symbol, Value,
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Literal(Value),
     Derived(Box<Instruction>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Add(Expr, Expr),
     Subtract(Expr, Expr),
@@ -26,103 +29,289 @@ pub enum Instruction {
     Return(Expr),
 }
 
-#[derive(Debug, PartialEq)]
+pub(crate) type BinaryInstruction = fn(Expr, Expr) -> Instruction;
+
+impl Instruction {
+    /// Splits an instruction with two operands into its constructor and operands,
+    /// so passes over the tree can handle every binary operator in one place.
+    pub(crate) fn into_binary(self) -> Result<(BinaryInstruction, Expr, Expr), Self> {
+        Ok(match self {
+            Instruction::Add(left, right) => (Instruction::Add, left, right),
+            Instruction::Subtract(left, right) => (Instruction::Subtract, left, right),
+            Instruction::Multiply(left, right) => (Instruction::Multiply, left, right),
+            Instruction::Divide(left, right) => (Instruction::Divide, left, right),
+            Instruction::Conditional(left, right) => (Instruction::Conditional, left, right),
+            Instruction::Colon(left, right) => (Instruction::Colon, left, right),
+            Instruction::NullishCoalescing(left, right) => {
+                (Instruction::NullishCoalescing, left, right)
+            }
+            Instruction::Equality(left, right) => (Instruction::Equality, left, right),
+            Instruction::Assignment(left, right) => (Instruction::Assignment, left, right),
+            instruction => return Err(instruction),
+        })
+    }
+}
+
+impl Instruction {
+    /// Whether [`Instruction::into_binary`] splits this instruction.
+    pub(crate) fn is_binary(&self) -> bool {
+        !matches!(
+            self,
+            Instruction::Access(_) | Instruction::Not(_) | Instruction::Return(_)
+        )
+    }
+
+    /// The operator this instruction is parsed from, or `None` for an access.
+    pub(crate) fn operator(&self) -> Option<Operator> {
+        Some(match self {
+            Instruction::Add(_, _) => Operator::Add,
+            Instruction::Subtract(_, _) => Operator::Subtract,
+            Instruction::Multiply(_, _) => Operator::Multiply,
+            Instruction::Divide(_, _) => Operator::Divide,
+            Instruction::Access(_) => return None,
+            Instruction::Conditional(_, _) => Operator::Conditional,
+            Instruction::Colon(_, _) => Operator::Colon,
+            Instruction::NullishCoalescing(_, _) => Operator::NullishCoalescing,
+            Instruction::Not(_) => Operator::Not,
+            Instruction::Equality(_, _) => Operator::Equality,
+            Instruction::Assignment(_, _) => Operator::Assignment,
+            Instruction::Return(_) => Operator::Return,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum AccessExpr {
-    Name(String),
+    Name(Symbol),
+    /// A root name resolved by [`crate::link`].
+    Slot(usize),
     Index(Expr),
     Call(Vec<Expr>),
 }
 
-pub fn treeify(mut tokens: &[Token]) -> Result<Expr, CompileError> {
-    if let [Token::OpenBracket, inner_tokens @ .., Token::CloseBracket] = tokens {
-        tokens = inner_tokens
-    }
+/// Where an expression was parsed from. A block's are listed in the order a
+/// [`crate::Visitor`] reaches its expressions.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExprSpan {
+    /// The whole expression, without brackets around it.
+    pub expr: Range<usize>,
+    /// The operator, or the whole expression if it has none.
+    pub operator: Range<usize>,
+    /// Each part of an access, such as `.name` or `(args)`.
+    pub accesses: Vec<Range<usize>>,
+}
+
+/// How many operators of a chain such as `a + b + c` count as one level of
+/// nesting. Compiling and evaluating a chain does not recurse per operator, but
+/// other passes over the tree do, with frames far smaller than nesting takes.
+pub(crate) const OPERATORS_PER_LEVEL: usize = 64;
+
+/// Builds an expression from `tokens`, failing once nesting goes `depth` levels
+/// deep, and lists where it and each expression inside came from in `spans`.
+///
+/// Brackets, calls, indexes, prefix operators and each part of an access nest
+/// a level. A chain of operators sharing a precedence, such as `a - b - c`, is
+/// split in one pass and nests a level per [`OPERATORS_PER_LEVEL`] operators.
+pub fn treeify(
+    tokens: &[Spanned<Token>],
+    depth: usize,
+    mut spans: Option<&mut Vec<ExprSpan>>,
+) -> Result<Expr, CompileError> {
+    let Some(inner) = depth.checked_sub(1) else {
+        return Err(CompileError::LimitExceeded(Limit::Depth));
+    };
 
-    let mut lowest_precidence_operator_maybe: Option<(usize, &Operator)> = None;
+    let tokens = strip_brackets(tokens);
+
+    // Every operator outside brackets with the lowest precedence, in order.
+    let mut lowest: Vec<(usize, Operator)> = Vec::new();
 
     let mut open_brackets = 0;
 
     for (i, token) in tokens.iter().enumerate() {
-        match token {
+        match &token.value {
             Token::OpenBracket => open_brackets += 1,
             Token::CloseBracket => open_brackets -= 1,
-            Token::Operator(op) if open_brackets == 0 => {
-                if let Some(lowest_precidence_operator) = lowest_precidence_operator_maybe {
-                    if (lowest_precidence_operator.1.precidence()) > (op.precidence()) {
-                        lowest_precidence_operator_maybe = Some((i, op));
-                    }
-                } else {
-                    lowest_precidence_operator_maybe = Some((i, op));
-                }
-            }
+            Token::Operator(op) if open_brackets == 0 => match lowest.first() {
+                Some((_, first)) if op.precidence() > first.precidence() => {}
+                Some((_, first)) if op.precidence() == first.precidence() => lowest.push((i, *op)),
+                _ => lowest = vec![(i, *op)],
+            },
             _ => {}
         }
     }
 
-    if let Some((i, op)) = lowest_precidence_operator_maybe {
-        let left = &tokens[..i];
-        let right = &tokens[i + 1..];
+    let Some(&(i, op)) = lowest.first() else {
+        push_span(&mut spans, tokens, None);
+        return treeify_operand(tokens, inner, spans);
+    };
 
-        return Ok(Expr::Derived(Box::new(match op {
-            Operator::Not => {
-                if !left.is_empty() {
-                    return Err(CompileError::TokensBeforePrefixOperator);
-                }
-                Instruction::Not(treeify(right)?)
-            }
-            Operator::Return => {
-                if !left.is_empty() {
-                    return Err(CompileError::TokensBeforePrefixOperator);
-                }
-                Instruction::Return(treeify(right)?)
-            }
-            Operator::Equality => Instruction::Equality(treeify(left)?, treeify(right)?),
-            Operator::Assignment => Instruction::Assignment(treeify(left)?, treeify(right)?),
-            Operator::Add => Instruction::Add(treeify(left)?, treeify(right)?),
-            Operator::Subtract => Instruction::Subtract(treeify(left)?, treeify(right)?),
-            Operator::Multiply => Instruction::Multiply(treeify(left)?, treeify(right)?),
-            Operator::Divide => Instruction::Divide(treeify(left)?, treeify(right)?),
-            Operator::Conditional => Instruction::Conditional(treeify(left)?, treeify(right)?),
-            Operator::Colon => Instruction::Colon(treeify(left)?, treeify(right)?),
-            Operator::NullishCoalescing => {
-                Instruction::NullishCoalescing(treeify(left)?, treeify(right)?)
+    if let Some(prefix) = prefix(op) {
+        if i != 0 {
+            return Err(CompileError::TokensBeforePrefixOperator);
+        }
+        push_span(&mut spans, tokens, Some(i));
+        return Ok(Expr::Derived(Box::new(prefix(treeify(
+            &tokens[i + 1..],
+            inner,
+            spans,
+        )?))));
+    }
+
+    // Operators group to the right, so `a - b - c` is `a - (b - c)`. A prefix
+    // operator ends the chain, leaving the rest as its operand.
+    let chain: Vec<(usize, BinaryInstruction)> = lowest
+        .iter()
+        .map_while(|&(i, op)| Some((i, infix(op)?)))
+        .collect();
+
+    let depth = depth
+        .checked_sub(chain.len() / OPERATORS_PER_LEVEL)
+        .ok_or(CompileError::LimitExceeded(Limit::Depth))?;
+
+    let mut links = Vec::new();
+    let mut start = 0;
+
+    for (i, infix) in chain {
+        push_span(&mut spans, &tokens[start..], Some(i - start));
+        links.push((
+            infix,
+            treeify(&tokens[start..i], depth, spans.as_deref_mut())?,
+        ));
+        start = i + 1;
+    }
+
+    let mut expr = treeify(&tokens[start..], depth, spans)?;
+
+    for (infix, left) in links.into_iter().rev() {
+        expr = Expr::Derived(Box::new(infix(left, expr)));
+    }
+
+    Ok(expr)
+}
+
+/// Lists where the expression in `tokens` came from, with its operator at
+/// `operator` if it has one.
+fn push_span(
+    spans: &mut Option<&mut Vec<ExprSpan>>,
+    tokens: &[Spanned<Token>],
+    operator: Option<usize>,
+) {
+    if let (Some(spans), [first, .., last] | [first @ last]) = (spans.as_deref_mut(), tokens) {
+        let expr = first.span.start..last.span.end;
+        spans.push(ExprSpan {
+            operator: match operator {
+                Some(i) => tokens[i].span.clone(),
+                None => expr.clone(),
+            },
+            accesses: match &first.value {
+                Token::Access(accesses) => accesses.iter().map(|a| a.span.clone()).collect(),
+                _ => Vec::new(),
+            },
+            expr,
+        });
+    }
+}
+
+/// Builds a literal or access, the only expressions without an operator.
+fn treeify_operand(
+    tokens: &[Spanned<Token>],
+    depth: usize,
+    mut spans: Option<&mut Vec<ExprSpan>>,
+) -> Result<Expr, CompileError> {
+    let [token] = tokens else {
+        return Err(CompileError::IncompleteExpression);
+    };
+    match &token.value {
+        Token::Number(n) => Ok(Expr::Literal(Value::Number(*n))),
+        Token::String(s) => Ok(Expr::Literal(Value::String(s.as_str().into()))),
+        Token::Access(accesses) => {
+            // Each part is a level of the structs an assignment builds or reads.
+            if accesses.len() > depth {
+                return Err(CompileError::LimitExceeded(Limit::Depth));
             }
-        })));
-    } else {
-        match tokens {
-            [Token::Number(n)] => return Ok(Expr::Literal(Value::Number(*n))),
-            [Token::String(s)] => return Ok(Expr::Literal(Value::String(s.clone()))),
-            [Token::Access(accesses)] => {
-                let mut access_exprs = Vec::new();
-                for access in accesses {
-                    match access {
-                        Access::Call(all_args_tokens) => {
-                            let mut args: Vec<Expr> = Vec::new();
-                            let args_tokens = comma_split(all_args_tokens);
-                            for arg_tokens in args_tokens {
-                                args.push(treeify(arg_tokens)?);
-                            }
-                            access_exprs.push(AccessExpr::Call(args));
-                        }
-                        Access::Name(name) => access_exprs.push(AccessExpr::Name(name.clone())),
-                        Access::Index(tokens) => {
-                            access_exprs.push(AccessExpr::Index(treeify(tokens)?))
+
+            let mut access_exprs = Vec::new();
+            for access in accesses {
+                match &access.value {
+                    Access::Call(all_args_tokens) => {
+                        let mut args: Vec<Expr> = Vec::new();
+                        let args_tokens = comma_split(all_args_tokens);
+                        for arg_tokens in args_tokens {
+                            args.push(treeify(arg_tokens, depth, spans.as_deref_mut())?);
                         }
+                        access_exprs.push(AccessExpr::Call(args));
                     }
+                    Access::Name(name) => access_exprs.push(AccessExpr::Name(name.clone())),
+                    Access::Index(tokens) => access_exprs.push(AccessExpr::Index(treeify(
+                        tokens,
+                        depth,
+                        spans.as_deref_mut(),
+                    )?)),
                 }
-                Ok(Expr::Derived(Box::new(Instruction::Access(access_exprs))))
             }
-            _ => Err(CompileError::IncompleteExpression),
+            Ok(Expr::Derived(Box::new(Instruction::Access(access_exprs))))
+        }
+        _ => Err(CompileError::IncompleteExpression),
+    }
+}
+
+fn prefix(op: Operator) -> Option<fn(Expr) -> Instruction> {
+    match op {
+        Operator::Not => Some(Instruction::Not),
+        Operator::Return => Some(Instruction::Return),
+        _ => None,
+    }
+}
+
+fn infix(op: Operator) -> Option<BinaryInstruction> {
+    Some(match op {
+        Operator::Equality => Instruction::Equality,
+        Operator::Assignment => Instruction::Assignment,
+        Operator::Add => Instruction::Add,
+        Operator::Subtract => Instruction::Subtract,
+        Operator::Multiply => Instruction::Multiply,
+        Operator::Divide => Instruction::Divide,
+        Operator::Conditional => Instruction::Conditional,
+        Operator::Colon => Instruction::Colon,
+        Operator::NullishCoalescing => Instruction::NullishCoalescing,
+        Operator::Not | Operator::Return => return None,
+    })
+}
+
+/// Removes brackets wrapping the whole of `tokens`, but not the pair in `(a) + (b)`.
+fn strip_brackets(mut tokens: &[Spanned<Token>]) -> &[Spanned<Token>] {
+    'strip: while let [open, inner @ .., close] = tokens {
+        if !matches!(
+            (&open.value, &close.value),
+            (Token::OpenBracket, Token::CloseBracket)
+        ) {
+            break;
+        }
+
+        let mut open_brackets = 0;
+
+        for token in inner {
+            match token.value {
+                Token::OpenBracket => open_brackets += 1,
+                Token::CloseBracket if open_brackets == 0 => break 'strip,
+                Token::CloseBracket => open_brackets -= 1,
+                _ => {}
+            }
         }
+
+        tokens = inner;
     }
+
+    tokens
 }
 
-fn comma_split<'a>(tokens: &'a Vec<Token>) -> Vec<&'a [Token]> {
+fn comma_split(tokens: &[Spanned<Token>]) -> Vec<&[Spanned<Token>]> {
     let mut result = Vec::new();
     let mut start = 0;
 
     for (i, token) in tokens.iter().enumerate() {
-        if let Token::Comma = token {
+        if let Token::Comma = token.value {
             result.push(&tokens[start..i]);
             start = i + 1;
         }
@@ -131,7 +320,7 @@ fn comma_split<'a>(tokens: &'a Vec<Token>) -> Vec<&'a [Token]> {
     result.push(&tokens[start..]);
 
     if let Some(last) = result.pop() {
-        if last.len() != 0 {
+        if !last.is_empty() {
             result.push(last);
         }
     }
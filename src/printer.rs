@@ -0,0 +1,435 @@
+use std::fmt::{Display, Formatter, Result};
+
+use thiserror::Error;
+
+use crate::{
+    blockiser::Block,
+    data::Operator,
+    parser::{AccessExpr, Instruction},
+    visitor::Visitor,
+    Expr, Number, Value,
+};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PrintError {
+    #[error("No literal syntax for `{0}`")]
+    NoLiteralSyntax(String),
+
+    #[error("Linked slot `#{0}` has no name to print")]
+    LinkedSlot(usize),
+}
+
+impl Block {
+    /// Prints the block as canonical Molang, failing rather than writing
+    /// anything which does not compile back to an equal block: values with no
+    /// literal syntax, such as negative numbers or strings containing `"`, and
+    /// the slots of linked blocks.
+    pub fn to_source(&self) -> std::result::Result<String, PrintError> {
+        let mut printable = Printable(Ok(()));
+        printable.visit_block(self);
+        printable.0.map(|()| self.to_string())
+    }
+}
+
+/// Finds the first node of a block which does not print as source.
+struct Printable(std::result::Result<(), PrintError>);
+
+impl Visitor for Printable {
+    fn visit_literal(&mut self, value: &Value) {
+        let literal = match value {
+            // `-0` prints as `0`, which is equal to it.
+            Value::Number(n) => n.is_finite() && *n >= 0.0,
+            Value::String(s) => !s.contains('"'),
+            _ => false,
+        };
+
+        if self.0.is_ok() && !literal {
+            self.0 = Err(PrintError::NoLiteralSyntax(format!("{value:?}")));
+        }
+    }
+
+    fn visit_slot(&mut self, slot: usize) {
+        if self.0.is_ok() {
+            self.0 = Err(PrintError::LinkedSlot(slot));
+        }
+    }
+}
+
+impl Display for Block {
+    /// Writes canonical Molang which compiles back to an equal block, except
+    /// as described for [`Expr`]; [`Block::to_source`] refuses those cases.
+    /// The alternate form, `{:#}`, leaves out every optional space.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 && !f.alternate() {
+                f.write_str(" ")?;
+            }
+            statement.fmt(f)?;
+            if self.multiple {
+                f.write_str(";")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Expr {
+    /// Writes canonical Molang with only the brackets precedence requires.
+    ///
+    /// Values with no literal syntax only come from folding and constants
+    /// folded in by the host, and do not round-trip. Negative and non-finite
+    /// numbers are written as arithmetic producing them, which compiles back to
+    /// that arithmetic rather than a literal. Strings are written between `"`
+    /// as they are, so one containing `"` no longer compiles. `null` is written
+    /// as `null`, which compiles back to an access of that name, and other
+    /// values in their `Debug` form. Slots of linked blocks are written as `#N`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Expr::Literal(value) => write_literal(f, value),
+            Expr::Derived(instruction) => write_instruction(f, instruction),
+        }
+    }
+}
+
+fn write_literal(f: &mut Formatter<'_>, value: &Value) -> Result {
+    match value {
+        Value::Number(n) => write_number(f, *n),
+        Value::String(s) => write!(f, "\"{s}\""),
+        Value::Null => f.write_str("null"),
+        value => write!(f, "{value:?}"),
+    }
+}
+
+fn write_number(f: &mut Formatter<'_>, n: Number) -> Result {
+    if n.is_nan() {
+        f.write_str(if f.alternate() { "(0/0)" } else { "(0 / 0)" })
+    } else if n.is_infinite() {
+        f.write_str(match (n > 0.0, f.alternate()) {
+            (true, false) => "(1 / 0)",
+            (true, true) => "(1/0)",
+            (false, false) => "(0 - 1 / 0)",
+            (false, true) => "(0-1/0)",
+        })
+    } else if n.is_sign_negative() && n != 0.0 {
+        if f.alternate() {
+            write!(f, "(0-{})", -n)
+        } else {
+            write!(f, "(0 - {})", -n)
+        }
+    } else {
+        write!(f, "{}", n.abs())
+    }
+}
+
+fn write_instruction(f: &mut Formatter<'_>, instruction: &Instruction) -> Result {
+    let op = match instruction.operator() {
+        Some(op) => op,
+        None => {
+            let Instruction::Access(accesses) = instruction else {
+                unreachable!()
+            };
+            return write_accesses(f, accesses);
+        }
+    };
+
+    match instruction {
+        Instruction::Not(expr) => {
+            f.write_str("!")?;
+            write_operand(f, expr, op, false)
+        }
+        Instruction::Return(expr) => {
+            f.write_str("return")?;
+            // Compactly, only a name or number would run into the keyword.
+            if !f.alternate()
+                || !needs_bracket(expr, op, false)
+                    && format!("{expr:#}").starts_with(char::is_alphanumeric)
+            {
+                f.write_str(" ")?;
+            }
+            write_operand(f, expr, op, false)
+        }
+        Instruction::Add(left, right)
+        | Instruction::Subtract(left, right)
+        | Instruction::Multiply(left, right)
+        | Instruction::Divide(left, right)
+        | Instruction::Conditional(left, right)
+        | Instruction::Colon(left, right)
+        | Instruction::NullishCoalescing(left, right)
+        | Instruction::Equality(left, right)
+        | Instruction::Assignment(left, right) => {
+            write_operand(f, left, op, true)?;
+            if f.alternate() {
+                f.write_str(symbol(op))?;
+            } else {
+                write!(f, " {} ", symbol(op))?;
+            }
+            write_operand(f, right, op, false)
+        }
+        Instruction::Access(_) => unreachable!(),
+    }
+}
+
+/// Brackets `expr` if it binds looser than `parent`, or as tightly when it is
+/// on the left, as operators sharing a precedence group to the right.
+fn write_operand(f: &mut Formatter<'_>, expr: &Expr, parent: Operator, against: bool) -> Result {
+    if needs_bracket(expr, parent, against) {
+        f.write_str("(")?;
+        expr.fmt(f)?;
+        f.write_str(")")
+    } else {
+        expr.fmt(f)
+    }
+}
+
+fn needs_bracket(expr: &Expr, parent: Operator, against: bool) -> bool {
+    let precidence = match expr {
+        Expr::Derived(instruction) => instruction.operator().map(|op| op.precidence()),
+        Expr::Literal(_) => None,
+    };
+
+    precidence.is_some_and(|precidence| {
+        precidence < parent.precidence() || (against && precidence == parent.precidence())
+    })
+}
+
+fn write_accesses(f: &mut Formatter<'_>, accesses: &[AccessExpr]) -> Result {
+    for (i, access) in accesses.iter().enumerate() {
+        match access {
+            AccessExpr::Name(name) if i == 0 => f.write_str(name)?,
+            AccessExpr::Name(name) => write!(f, ".{name}")?,
+            AccessExpr::Slot(slot) => write!(f, "#{slot}")?,
+            AccessExpr::Index(index) => {
+                f.write_str("[")?;
+                index.fmt(f)?;
+                f.write_str("]")?;
+            }
+            AccessExpr::Call(args) => {
+                f.write_str("(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(if f.alternate() { "," } else { ", " })?;
+                    }
+                    arg.fmt(f)?;
+                }
+                f.write_str(")")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn symbol(op: Operator) -> &'static str {
+    match op {
+        Operator::NullishCoalescing => "??",
+        Operator::Conditional => "?",
+        Operator::Colon => ":",
+        Operator::Divide => "/",
+        Operator::Multiply => "*",
+        Operator::Add => "+",
+        Operator::Subtract => "-",
+        Operator::Not => "!",
+        Operator::Assignment => "=",
+        Operator::Equality => "==",
+        Operator::Return => "return",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use crate::{
+        blockiser::Block,
+        compile, link,
+        parser::{AccessExpr, Instruction},
+        Expr, Number, Schema, Value,
+    };
+
+    use super::PrintError;
+
+    fn name() -> impl Strategy<Value = String> {
+        "[a-z][a-z0-9_]{0,4}".prop_filter("keyword", |name| name != "return")
+    }
+
+    fn number() -> impl Strategy<Value = Number> {
+        prop_oneof![
+            4 => (0..1000u32).prop_map(|n| n as Number / 4.0),
+            1 => (1..1000u32).prop_map(|n| -(n as Number) / 4.0),
+            1 => prop::sample::select(vec![-0.0, Number::NAN, Number::INFINITY, Number::NEG_INFINITY]),
+        ]
+    }
+
+    fn leaf() -> impl Strategy<Value = Expr> {
+        prop_oneof![
+            number().prop_map(|n| Expr::Literal(Value::Number(n))),
+            "[a-z \"]{0,3}".prop_map(|s| Expr::Literal(Value::String(s.into()))),
+            prop::collection::vec(name(), 1..3).prop_map(|names| {
+                Expr::Derived(Box::new(Instruction::Access(
+                    names
+                        .into_iter()
+                        .map(|name| AccessExpr::Name(name.into()))
+                        .collect(),
+                )))
+            }),
+        ]
+    }
+
+    fn expr() -> impl Strategy<Value = Expr> {
+        leaf().prop_recursive(6, 48, 3, |inner| {
+            let binary = |f: fn(Expr, Expr) -> Instruction| {
+                (inner.clone(), inner.clone())
+                    .prop_map(move |(l, r)| Expr::Derived(Box::new(f(l, r))))
+            };
+
+            prop_oneof![
+                binary(Instruction::Add),
+                binary(Instruction::Subtract),
+                binary(Instruction::Multiply),
+                binary(Instruction::Divide),
+                binary(Instruction::Conditional),
+                binary(Instruction::Colon),
+                binary(Instruction::NullishCoalescing),
+                binary(Instruction::Equality),
+                binary(Instruction::Assignment),
+                inner
+                    .clone()
+                    .prop_map(|e| Expr::Derived(Box::new(Instruction::Not(e)))),
+                inner
+                    .clone()
+                    .prop_map(|e| Expr::Derived(Box::new(Instruction::Return(e)))),
+                (
+                    name(),
+                    inner.clone(),
+                    prop::collection::vec(inner.clone(), 0..3)
+                )
+                    .prop_map(|(name, index, args)| {
+                        Expr::Derived(Box::new(Instruction::Access(vec![
+                            AccessExpr::Name(name.into()),
+                            AccessExpr::Index(index),
+                            AccessExpr::Name("f".into()),
+                            AccessExpr::Call(args),
+                        ])))
+                    }),
+            ]
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig {
+            cases: if cfg!(miri) { 4 } else { 256 },
+            failure_persistence: None,
+            ..ProptestConfig::default()
+        })]
+
+        #[test]
+        fn round_trip(statements in prop::collection::vec(expr(), 1..4), multiple: bool) {
+            let block = Block {
+                multiple: multiple || statements.len() > 1,
+                statements,
+            };
+            let source = block.to_string();
+            let compact = format!("{block:#}");
+
+            // Refused exactly when printing would not give the block back.
+            match block.to_source() {
+                Ok(printed) => {
+                    prop_assert_eq!(&printed, &source);
+                    prop_assert_eq!(&block, &compile(&source).unwrap(), "{}", source);
+                    prop_assert_eq!(&block, &compile(&compact).unwrap(), "{}", compact);
+                }
+                Err(e) => {
+                    prop_assert!(matches!(e, PrintError::NoLiteralSyntax(_)));
+                    prop_assert_ne!(Ok(block), compile(&source), "{}", source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn minimal_brackets() {
+        for source in [
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "(10 - 2) - 3",
+            "10 - 2 - 3",
+            "(a = b) = c",
+            "a ? b : c ? d : e",
+            "(a ? b : c) ? d : e",
+            "!(a == b)",
+            "v.x = q.f(1, \"s\")[2]",
+            "a; return b;",
+        ] {
+            assert_eq!(source, compile(source).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn compact() {
+        for (source, compact) in [
+            ("1 + 2 * 3", "1+2*3"),
+            ("return (1 + 2) * 3", "return(1+2)*3"),
+            ("return !v.a", "return!v.a"),
+            ("return v.a; return 2;", "return v.a;return 2;"),
+            ("v.x = q.f(1, \"s\")[2]", "v.x=q.f(1,\"s\")[2]"),
+        ] {
+            assert_eq!(compact, format!("{:#}", compile(source).unwrap()));
+        }
+    }
+
+    #[test]
+    fn negative() {
+        let block = Block {
+            multiple: false,
+            statements: vec![Expr::Derived(Box::new(Instruction::Multiply(
+                Expr::Literal(Value::Number(-1.5)),
+                Expr::Literal(Value::Number(2.0)),
+            )))],
+        };
+
+        assert_eq!("(0 - 1.5) * 2", block.to_string());
+        assert!(matches!(
+            block.to_source(),
+            Err(PrintError::NoLiteralSyntax(_))
+        ));
+    }
+
+    #[test]
+    fn no_literal_syntax() {
+        let source = "v.x ?? 1";
+        assert_eq!(Ok(source.to_string()), compile(source).unwrap().to_source());
+
+        let block = Block {
+            multiple: false,
+            statements: vec![Expr::Derived(Box::new(Instruction::NullishCoalescing(
+                Expr::Literal(Value::Null),
+                Expr::Literal(Value::Number(1.0)),
+            )))],
+        };
+
+        assert_eq!("null ?? 1", block.to_string());
+        assert_eq!("null??1", format!("{block:#}"));
+        assert!(matches!(
+            block.to_source(),
+            Err(PrintError::NoLiteralSyntax(_))
+        ));
+
+        let quoted = Block {
+            multiple: false,
+            statements: vec![Expr::Literal(Value::String("say \"hi\"".into()))],
+        };
+        assert!(matches!(
+            quoted.to_source(),
+            Err(PrintError::NoLiteralSyntax(_))
+        ));
+    }
+
+    #[test]
+    fn linked_slots() {
+        let mut schema = Schema::new();
+        schema.variable("v");
+        let block = link(compile("v.x + 1").unwrap(), &schema).unwrap();
+
+        assert_eq!("#0.x + 1", block.to_string());
+        assert_eq!(Err(PrintError::LinkedSlot(0)), block.to_source());
+    }
+}
@@ -0,0 +1,370 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    blockiser::Block,
+    compile_spanned,
+    optimiser::optimise_expr,
+    parser::{ExprSpan, Instruction},
+    tokeniser::{lex, statement_spans},
+    visitor::{walk_expr, walk_instruction, Visitor},
+    CompileError, Expr, Limits, Value,
+};
+
+/// Code which can never run, found without running the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unreachable {
+    /// The statement at this index, which follows one that always returns.
+    Statement(usize),
+    /// A branch of a ternary whose condition is constant.
+    Branch {
+        statement: usize,
+        /// Which ternary of the statement, counting its `?`s in source order.
+        ternary: usize,
+        /// Whether the branch never taken is the one before the `:`.
+        if_true: bool,
+    },
+}
+
+/// Finds the statements and ternary branches of `block` which never run, with
+/// conditions folded as [`crate::optimise`] would fold them. Nothing inside
+/// code already found unreachable is reported again.
+///
+/// A compiled block has no spans, so findings are located by ordinal; use
+/// [`unreachable_spans`] or [`crate::lint`] to find them in the source.
+pub fn unreachable_code(
+    block: &Block,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Vec<Unreachable> {
+    find(block, constants, aliases)
+        .into_iter()
+        .map(|found| found.unreachable)
+        .collect()
+}
+
+/// Compiles `source` and finds its unreachable code as [`unreachable_code`]
+/// does, with the byte range of each statement or branch which never runs.
+pub fn unreachable_spans(
+    source: &str,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Result<Vec<(Unreachable, Range<usize>)>, CompileError> {
+    let mut exprs = Vec::new();
+    let block = compile_spanned(source, &Limits::default(), None, Some(&mut exprs))?;
+    let statements = statement_spans(&lex(source).map_err(CompileError::TokeniseError)?);
+
+    Ok(find(&block, constants, aliases)
+        .into_iter()
+        .map(|found| {
+            let span = found.span(&exprs, &statements).unwrap_or(0..source.len());
+            (found.unreachable, span)
+        })
+        .collect())
+}
+
+/// An [`Unreachable`], with the expression it refers to.
+pub(crate) struct Found {
+    pub unreachable: Unreachable,
+    /// For a branch, its index among the expressions [`compile_spanned`]
+    /// lists spans for.
+    expr: Option<usize>,
+}
+
+impl Found {
+    /// Where the code which never runs was parsed from, given the spans of
+    /// every expression and statement of the block.
+    pub fn span(&self, exprs: &[ExprSpan], statements: &[Range<usize>]) -> Option<Range<usize>> {
+        match (self.unreachable, self.expr) {
+            (Unreachable::Statement(statement), _) => statements.get(statement).cloned(),
+            (Unreachable::Branch { .. }, Some(expr)) => exprs.get(expr).map(|e| e.expr.clone()),
+            (Unreachable::Branch { .. }, None) => None,
+        }
+    }
+}
+
+pub(crate) fn find(
+    block: &Block,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Vec<Found> {
+    let mut found = Vec::new();
+    let mut branches = Branches {
+        constants,
+        aliases,
+        statement: 0,
+        ternaries: 0,
+        next: 0,
+        dead: false,
+        found: &mut found,
+    };
+
+    for (index, statement) in block.statements.iter().enumerate() {
+        branches.statement = index;
+        branches.ternaries = 0;
+        branches.visit_expr(statement);
+
+        if always_returns(statement, constants, aliases) {
+            branches
+                .found
+                .extend((index + 1..block.statements.len()).map(|statement| Found {
+                    unreachable: Unreachable::Statement(statement),
+                    expr: None,
+                }));
+            break;
+        }
+    }
+
+    found
+}
+
+struct Branches<'a> {
+    constants: &'a HashMap<String, Value>,
+    aliases: &'a HashMap<String, String>,
+    statement: usize,
+    /// Ternaries seen so far in the statement, reported or not.
+    ternaries: usize,
+    /// The index of the next expression visited, in the order
+    /// [`compile_spanned`] lists their spans.
+    next: usize,
+    /// Set while inside a branch already reported.
+    dead: bool,
+    found: &'a mut Vec<Found>,
+}
+
+impl Branches<'_> {
+    /// Places the branch reported at `found`, which is visited next.
+    fn locate(&mut self, found: Option<usize>) {
+        if let Some(found) = found {
+            self.found[found].expr = Some(self.next);
+        }
+    }
+
+    fn visit_branch(&mut self, expr: &Expr, dead: bool) {
+        let outer = self.dead;
+        self.dead |= dead;
+        self.visit_expr(expr);
+        self.dead = outer;
+    }
+}
+
+impl Visitor for Branches<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.next += 1;
+        walk_expr(self, expr)
+    }
+
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        let Instruction::Conditional(condition, branches) = instruction else {
+            return walk_instruction(self, instruction);
+        };
+
+        self.visit_expr(condition);
+        let ternary = self.ternaries;
+        self.ternaries += 1;
+
+        let Some((if_true, if_false)) = colon(branches) else {
+            return self.visit_expr(branches);
+        };
+
+        // The `:` holding the branches.
+        self.next += 1;
+
+        let taken = constant(condition, self.constants, self.aliases);
+        // Reported before anything inside it, and located once it is reached.
+        let reported = match (taken, self.dead) {
+            (Some(taken), false) => {
+                self.found.push(Found {
+                    unreachable: Unreachable::Branch {
+                        statement: self.statement,
+                        ternary,
+                        if_true: !taken,
+                    },
+                    expr: None,
+                });
+                Some(self.found.len() - 1)
+            }
+            _ => None,
+        };
+
+        if taken == Some(false) {
+            self.locate(reported);
+        }
+        self.visit_branch(if_true, taken == Some(false));
+        if taken == Some(true) {
+            self.locate(reported);
+        }
+        self.visit_branch(if_false, taken == Some(true));
+    }
+}
+
+/// Whether evaluating `expr` always ends in a `return`.
+fn always_returns(
+    expr: &Expr,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> bool {
+    let returns = |expr| always_returns(expr, constants, aliases);
+
+    let Expr::Derived(instruction) = expr else {
+        return false;
+    };
+
+    match instruction.as_ref() {
+        Instruction::Return(_) => true,
+        Instruction::Add(left, right)
+        | Instruction::Subtract(left, right)
+        | Instruction::Multiply(left, right)
+        | Instruction::Divide(left, right)
+        | Instruction::Equality(left, right) => returns(left) || returns(right),
+        // Only the left of `??` always runs.
+        Instruction::Not(expr) | Instruction::NullishCoalescing(expr, _) => returns(expr),
+        Instruction::Assignment(left, right) => {
+            matches!(left, Expr::Derived(i) if matches!(**i, Instruction::Access(_)))
+                && returns(right)
+        }
+        Instruction::Conditional(condition, branches) => {
+            returns(condition)
+                || match (constant(condition, constants, aliases), colon(branches)) {
+                    (Some(true), Some((if_true, _))) => returns(if_true),
+                    (Some(false), Some((_, if_false))) => returns(if_false),
+                    (None, Some((if_true, if_false))) => returns(if_true) && returns(if_false),
+                    (_, None) => false,
+                }
+        }
+        Instruction::Colon(_, _) | Instruction::Access(_) => false,
+    }
+}
+
+/// The value of a condition which folds to a number.
+pub(crate) fn constant(
+    condition: &Expr,
+    constants: &HashMap<String, Value>,
+    aliases: &HashMap<String, String>,
+) -> Option<bool> {
+    match optimise_expr(condition.clone(), constants, aliases) {
+        Expr::Literal(Value::Number(n)) => Some(n != 0.0),
+        _ => None,
+    }
+}
+
+/// The branches of a ternary, which fails at runtime without them.
+pub(crate) fn colon(branches: &Expr) -> Option<(&Expr, &Expr)> {
+    match branches {
+        Expr::Derived(i) => match i.as_ref() {
+            Instruction::Colon(if_true, if_false) => Some((if_true, if_false)),
+            _ => None,
+        },
+        Expr::Literal(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{compile, Runtime, Value};
+
+    use super::{unreachable_code, unreachable_spans, Unreachable};
+
+    fn unreachable(source: &str) -> Vec<Unreachable> {
+        unreachable_code(&compile(source).unwrap(), &HashMap::new(), &HashMap::new())
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(
+            vec![Unreachable::Statement(2), Unreachable::Statement(3)],
+            unreachable("v.a = 1; return v.a; v.b = 2; v.c = 3;")
+        );
+        assert_eq!(
+            vec![Unreachable::Statement(1)],
+            unreachable("q.a ? (return 1) : (return 2); v.b = 2;")
+        );
+        assert_eq!(
+            vec![Unreachable::Statement(1)],
+            unreachable("v.x = 1 + (return 2); v.b = 2;")
+        );
+
+        // Either branch may not return, and `??` may skip its right.
+        assert!(unreachable("q.a ? (return 1) : 0; v.b = 2;").is_empty());
+        assert!(unreachable("q.a ?? (return 1); v.b = 2;").is_empty());
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(
+            vec![
+                Unreachable::Branch {
+                    statement: 0,
+                    ternary: 0,
+                    if_true: false
+                },
+                Unreachable::Branch {
+                    statement: 1,
+                    ternary: 1,
+                    if_true: true
+                },
+            ],
+            unreachable(
+                "v.a = (1 + 1) ? 2 : (0 ? 3 : 4); v.b = (q.a ? 1 : 2) + ((1 == 2) ? 3 : 4);"
+            )
+        );
+
+        // The branch always taken returns, so the next statement never runs.
+        assert_eq!(
+            vec![
+                Unreachable::Branch {
+                    statement: 0,
+                    ternary: 0,
+                    if_true: false
+                },
+                Unreachable::Statement(1),
+            ],
+            unreachable("!0 ? (return 1) : v.a; v.b = 2;")
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let runtime = Runtime::builder()
+            .constant("debug", Value::Number(0.0))
+            .alias("c", "debug")
+            .build();
+
+        let block = runtime.compile("c ? (return 1) : 0; v.b = 2;").unwrap();
+        assert_eq!(
+            vec![Unreachable::Branch {
+                statement: 0,
+                ternary: 0,
+                if_true: true
+            }],
+            runtime.unreachable_code(&block)
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let source = "v.a = (q.a ? 1) + (1 ? 2 : (3)); return 1; v.b = 2;";
+        let found: Vec<(Unreachable, &str)> =
+            unreachable_spans(source, &HashMap::new(), &HashMap::new())
+                .unwrap()
+                .into_iter()
+                .map(|(unreachable, span)| (unreachable, &source[span]))
+                .collect();
+
+        assert_eq!(
+            vec![
+                (
+                    Unreachable::Branch {
+                        statement: 0,
+                        ternary: 1,
+                        if_true: false
+                    },
+                    "3"
+                ),
+                (Unreachable::Statement(2), "v.b = 2;"),
+            ],
+            found
+        );
+    }
+}
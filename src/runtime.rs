@@ -0,0 +1,794 @@
+use std::{collections::HashMap, fmt::Debug, time::Instant};
+
+#[cfg(feature = "sync")]
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::{
+    check_types,
+    columnar::{Program, Row},
+    compile_interned, dependencies, estimate_cost,
+    interpreter::{run_scoped, Scope},
+    limits::Budget,
+    optimise,
+    optimiser::resolve_aliases,
+    sync::{shared, MaybeSend},
+    unreachable_code, Block, CancelHandle, Columns, CompileError, CostModel, Dependencies,
+    External, Function, Interner, Limits, MolangEq, MolangError, Number, Signatures, ToMolangValue,
+    Type, TypeError, Unreachable, Value, VariableStore,
+};
+
+/// Supplies values for a namespace such as `query`, computed on demand.
+///
+/// `q.name` calls `query("name", vec![])` and `q.name(a, b)` calls `query("name", vec![a, b])`.
+/// Errors from either fail the script, so return `Value::Null` for names
+/// which should read as null.
+pub trait QueryProvider: MaybeSend {
+    fn query(&mut self, name: &str, args: Vec<Value>) -> Result<Value, MolangError>;
+}
+
+impl<F> QueryProvider for F
+where
+    F: FnMut(&str, Vec<Value>) -> Result<Value, MolangError> + MaybeSend,
+{
+    fn query(&mut self, name: &str, args: Vec<Value>) -> Result<Value, MolangError> {
+        self(name, args)
+    }
+}
+
+struct Queries {
+    namespace: String,
+    provider: Box<dyn QueryProvider>,
+}
+
+impl Debug for Queries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Queries({})", self.namespace)
+    }
+}
+
+impl MolangEq for Queries {
+    /// Only equal to itself, which [`Value`] checks before asking.
+    fn molang_eq(&self, _rhs: &Value) -> bool {
+        false
+    }
+}
+
+impl External for Queries {
+    fn get(&mut self, property: &str) -> Value {
+        // `get` cannot fail, so a query which errors without arguments reads as null.
+        self.try_get(property).unwrap_or(Value::Null)
+    }
+
+    fn try_get(&mut self, property: &str) -> Result<Value, MolangError> {
+        self.provider.query(property, Vec::new())
+    }
+
+    fn set(&mut self, property: &str, _value: Value) -> Result<(), MolangError> {
+        Err(MolangError::NotAssignable(format!(
+            "{}.{property}",
+            self.namespace
+        )))
+    }
+
+    fn call_function(&mut self, function: &str, args: Vec<Value>) -> Result<Value, MolangError> {
+        self.provider.query(function, args)
+    }
+
+    fn index_get(&mut self, _index: Value) -> Result<Value, MolangError> {
+        Err(MolangError::BadAccess(
+            "[]".to_string(),
+            self.namespace.clone(),
+        ))
+    }
+
+    fn index_set(&mut self, _index: Value, _value: Value) -> Result<(), MolangError> {
+        Err(MolangError::BadAccess(
+            "[]".to_string(),
+            self.namespace.clone(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Run [`optimise`] over blocks compiled by [`Runtime::compile`].
+    pub optimise: bool,
+}
+
+/// Owns everything a script runs against: constants, functions, query
+/// providers, aliases and variables.
+pub struct Runtime {
+    constants: HashMap<String, Value>,
+    variables: HashMap<String, Value>,
+    aliases: HashMap<String, String>,
+    limits: Limits,
+    options: Options,
+    cancel: CancelHandle,
+    deadline: Option<Instant>,
+    interner: Interner,
+}
+
+impl Runtime {
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Block, CompileError> {
+        let block = compile_interned(source, &self.limits, &self.interner)?;
+
+        Ok(if self.options.optimise {
+            optimise(block, &self.constants, &self.aliases)
+        } else {
+            block
+        })
+    }
+
+    pub fn eval(&mut self, block: &Block) -> Result<Value, MolangError> {
+        run_scoped(
+            block,
+            &mut Scope {
+                constants: &self.constants,
+                variables: &mut self.variables,
+                aliases: &self.aliases,
+                slots: &mut [],
+                budget: Budget::new(&self.limits).interruptible(&self.cancel, self.deadline),
+            },
+        )
+    }
+
+    /// Evaluates against host-owned variables instead of the runtime's own.
+    pub fn eval_with(
+        &self,
+        block: &Block,
+        variables: &mut dyn VariableStore,
+    ) -> Result<Value, MolangError> {
+        run_scoped(
+            block,
+            &mut Scope {
+                constants: &self.constants,
+                variables,
+                aliases: &self.aliases,
+                slots: &mut [],
+                budget: Budget::new(&self.limits).interruptible(&self.cancel, self.deadline),
+            },
+        )
+    }
+
+    /// Runs a block produced by [`crate::link`] through `slots`, under this
+    /// runtime's limits, cancel handle and deadline.
+    pub fn eval_linked(&self, block: &Block, slots: &mut [Value]) -> Result<Value, MolangError> {
+        run_scoped(
+            block,
+            &mut Scope {
+                constants: &HashMap::new(),
+                variables: &mut HashMap::new(),
+                aliases: &HashMap::new(),
+                slots,
+                budget: Budget::new(&self.limits).interruptible(&self.cancel, self.deadline),
+            },
+        )
+    }
+
+    /// Evaluates `block` once per context, writing each result to the same
+    /// position in `out`, e.g. one expression for every particle in a system.
+    ///
+    /// Aliases are resolved, and constants folded if [`Options::optimise`] is
+    /// set, once for the whole batch. Stops at the first error, with results
+    /// for earlier contexts already written. Returns how many were evaluated:
+    /// the shorter of `contexts` and `out`.
+    pub fn eval_batch<'c, S>(
+        &self,
+        block: &Block,
+        contexts: impl IntoIterator<Item = &'c mut S>,
+        out: &mut [Value],
+    ) -> Result<usize, MolangError>
+    where
+        S: VariableStore + 'c,
+    {
+        let block = self.prepare(block);
+        let aliases = HashMap::new();
+        let mut evaluated = 0;
+
+        for (variables, result) in contexts.into_iter().zip(out.iter_mut()) {
+            *result = run_scoped(
+                &block,
+                &mut Scope {
+                    constants: &self.constants,
+                    variables,
+                    aliases: &aliases,
+                    slots: &mut [],
+                    budget: Budget::new(&self.limits).interruptible(&self.cancel, self.deadline),
+                },
+            )?;
+            evaluated += 1;
+        }
+
+        Ok(evaluated)
+    }
+
+    /// Evaluates a numeric `block` once per row of `columns` into `out`, e.g.
+    /// `q.anim_time * 40 + v.offset` for every bone.
+    ///
+    /// Arithmetic over numbers and columns runs a chunk of rows per operation.
+    /// Anything else falls back to the interpreter row by row, with assignments
+    /// kept only for that row. Either way [`Limits::max_steps`] bounds the
+    /// whole call, counting each expression once per row.
+    pub fn eval_columns(
+        &self,
+        block: &Block,
+        columns: &Columns,
+        out: &mut [Number],
+    ) -> Result<(), MolangError> {
+        columns.check(out.len())?;
+
+        let block = self.prepare(block);
+        let budget = || Budget::new(&self.limits).interruptible(&self.cancel, self.deadline);
+
+        if let Some(program) = Program::compile(&block, &self.constants, columns) {
+            return program.run(columns, out, &mut budget());
+        }
+
+        let mut steps = 0;
+
+        let aliases = HashMap::new();
+
+        for (row, result) in out.iter_mut().enumerate() {
+            let mut variables = Row {
+                columns,
+                row,
+                scratch: HashMap::new(),
+            };
+
+            let mut scope = Scope {
+                constants: &self.constants,
+                variables: &mut variables,
+                aliases: &aliases,
+                slots: &mut [],
+                budget: budget().resume(steps),
+            };
+            let value = run_scoped(&block, &mut scope)?;
+            steps = scope.budget.taken();
+
+            *result = match value {
+                Value::Number(n) => n,
+                a => {
+                    return Err(MolangError::TypeError(
+                        "Number".to_string(),
+                        format!("{a:?}"),
+                    ))
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Resolves aliases, so `block` runs without them, and folds constants
+    /// if [`Options::optimise`] is set.
+    pub(crate) fn prepare(&self, block: &Block) -> Block {
+        let block = if self.options.optimise {
+            optimise(block.clone(), &self.constants, &self.aliases)
+        } else {
+            block.clone()
+        };
+        resolve_aliases(block, &self.aliases)
+    }
+
+    /// What `block` reads, writes and calls, with this runtime's aliases resolved.
+    pub fn dependencies(&self, block: &Block) -> Dependencies {
+        dependencies(block, &self.aliases)
+    }
+
+    /// Checks `block` against `signatures`, with this runtime's constants
+    /// typed by their values unless declared.
+    pub fn check_types(&self, block: &Block, signatures: &Signatures) -> Vec<TypeError> {
+        let mut signatures = signatures.clone();
+        for (name, value) in &self.constants {
+            signatures = signatures.constant_default(name, Type::of(value));
+        }
+        check_types(block, &signatures, &self.aliases)
+    }
+
+    /// The code in `block` which never runs, with this runtime's constants
+    /// deciding conditions.
+    pub fn unreachable_code(&self, block: &Block) -> Vec<Unreachable> {
+        unreachable_code(block, &self.constants, &self.aliases)
+    }
+
+    /// The most `block` can cost in one run under `model`, with this
+    /// runtime's constants deciding conditions.
+    pub fn estimate_cost(&self, block: &Block, model: &CostModel) -> u64 {
+        estimate_cost(block, model, &self.constants, &self.aliases)
+    }
+
+    /// Evaluations after `deadline` fail with [`MolangError::Cancelled`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// A handle which cancels this runtime's evaluations, usable from other threads.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, MolangError> {
+        let block = self.compile(source)?;
+        self.eval(&block)
+    }
+
+    pub fn constants(&self) -> &HashMap<String, Value> {
+        &self.constants
+    }
+
+    pub fn variables(&self) -> &HashMap<String, Value> {
+        &self.variables
+    }
+
+    pub fn variables_mut(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.variables
+    }
+
+    pub fn aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    /// The symbol table shared by every block this runtime compiles.
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+}
+
+#[cfg(feature = "sync")]
+impl Runtime {
+    /// Evaluates independent `(block, variables)` jobs across scoped threads,
+    /// e.g. every entity in a server tick.
+    ///
+    /// Results come back in job order, each job's error on its own. A job
+    /// which panics, e.g. in a host function, gives [`MolangError::Panicked`]
+    /// and may leave its variables part written; the other jobs are
+    /// unaffected. Jobs are split into one contiguous run per available core.
+    ///
+    /// Each host function and external is behind a `Mutex`, so calls to the
+    /// same one are serialised across jobs, and one which evaluates a script
+    /// calling itself again deadlocks.
+    pub fn eval_parallel<S>(&self, jobs: &mut [(&Block, S)]) -> Vec<Result<Value, MolangError>>
+    where
+        S: VariableStore + Send,
+    {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = jobs.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = jobs
+                .chunks_mut(chunk)
+                .map(|jobs| {
+                    scope.spawn(move || {
+                        jobs.iter_mut()
+                            .map(|(block, variables)| {
+                                catch_unwind(AssertUnwindSafe(|| self.eval_with(block, variables)))
+                                    .unwrap_or_else(|panic| {
+                                        Err(MolangError::Panicked(panic_message(&*panic)))
+                                    })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| match worker.join() {
+                    Ok(results) => results,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect()
+        })
+    }
+}
+
+/// The message a panic was raised with, if it was a string.
+#[cfg(feature = "sync")]
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Builds a [`Runtime`]. Names may be dotted, e.g. `math.max`, to nest them in structs.
+#[derive(Default)]
+pub struct RuntimeBuilder {
+    constants: HashMap<String, Value>,
+    variables: HashMap<String, Value>,
+    aliases: HashMap<String, String>,
+    limits: Limits,
+    options: Options,
+    cancel: CancelHandle,
+}
+
+impl RuntimeBuilder {
+    pub fn constant(mut self, name: &str, value: impl ToMolangValue) -> Self {
+        insert_path(&mut self.constants, name, value.to_value());
+        self
+    }
+
+    pub fn function(
+        self,
+        name: &str,
+        f: impl FnMut(Vec<Value>) -> Result<Value, MolangError> + MaybeSend + 'static,
+    ) -> Self {
+        self.constant(name, Value::Function(Function { f: shared(f) }))
+    }
+
+    pub fn query_provider(self, name: &str, provider: impl QueryProvider + 'static) -> Self {
+        self.constant(
+            name,
+            Value::External(shared(Queries {
+                namespace: name.to_string(),
+                provider: Box::new(provider),
+            })),
+        )
+    }
+
+    pub fn variable(mut self, name: &str, value: impl ToMolangValue) -> Self {
+        insert_path(&mut self.variables, name, value.to_value());
+        self
+    }
+
+    pub fn alias(mut self, alias: &str, name: &str) -> Self {
+        self.aliases.insert(alias.to_string(), name.to_string());
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn optimise(mut self, optimise: bool) -> Self {
+        self.options.optimise = optimise;
+        self
+    }
+
+    pub fn cancel_handle(mut self, cancel: CancelHandle) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn build(self) -> Runtime {
+        Runtime {
+            constants: self.constants,
+            variables: self.variables,
+            aliases: self.aliases,
+            limits: self.limits,
+            options: self.options,
+            cancel: self.cancel,
+            deadline: None,
+            interner: Interner::new(),
+        }
+    }
+}
+
+fn insert_path(map: &mut HashMap<String, Value>, path: &str, value: Value) {
+    let (head, rest) = match path.split_once('.') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (path, None),
+    };
+
+    match rest {
+        None => {
+            map.insert(head.to_string(), value);
+        }
+        Some(rest) => {
+            let entry = map
+                .entry(head.to_string())
+                .or_insert_with(|| Value::Struct(HashMap::new()));
+
+            if !matches!(entry, Value::Struct(_)) {
+                *entry = Value::Struct(HashMap::new());
+            }
+
+            if let Value::Struct(struc) = entry {
+                insert_path(struc, rest, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        compile, link, CompileError, Limit, Limits, MolangError, Number, Schema, Value,
+        VariableStore,
+    };
+
+    use super::Runtime;
+
+    #[test]
+    fn builder() {
+        let mut runtime = Runtime::builder()
+            .constant("math.pi", 3.0)
+            .function("math.max", |args| {
+                Ok(Value::Number(args.into_iter().fold(Number::MIN, |a, b| {
+                    if let Value::Number(b) = b {
+                        a.max(b)
+                    } else {
+                        a
+                    }
+                })))
+            })
+            .variable("variable", Value::Struct(HashMap::new()))
+            .alias("v", "variable")
+            .build();
+
+        assert_eq!(
+            Value::Number(15.0),
+            runtime
+                .eval_str("v.x = math.max(1, 5, 2) * math.pi; return v.x;")
+                .unwrap()
+        );
+        assert_eq!(
+            Value::Number(15.0),
+            runtime.eval(&compile("variable.x").unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn queries() {
+        let mut runtime = Runtime::builder()
+            .query_provider("query", |name: &str, args: Vec<Value>| match name {
+                "anim_time" => Ok(Value::Number(2.0)),
+                "double" => match args.as_slice() {
+                    [Value::Number(n)] => Ok(Value::Number(n * 2.0)),
+                    _ => Err(MolangError::FunctionError("Expected a number".into())),
+                },
+                _ => Err(MolangError::FunctionNotFound(name.to_string())),
+            })
+            .alias("q", "query")
+            .build();
+
+        assert_eq!(
+            Value::Number(8.0),
+            runtime.eval_str("q.double(q.anim_time) * 2").unwrap()
+        );
+        // Errors are the script's, whether or not the query is called.
+        assert!(matches!(
+            runtime.eval_str("q.missing"),
+            Err(MolangError::FunctionNotFound(_))
+        ));
+        assert!(matches!(
+            runtime.eval_str("q.missing()"),
+            Err(MolangError::FunctionNotFound(_))
+        ));
+        assert!(matches!(
+            runtime.eval_str("q.anim_time = 1"),
+            Err(MolangError::NotAssignable(_))
+        ));
+    }
+
+    #[test]
+    fn queries_run_once() {
+        let calls = crate::shared(Vec::new());
+        let log = calls.clone();
+        let mut runtime = Runtime::builder()
+            .query_provider("query", move |name: &str, args: Vec<Value>| {
+                crate::lock(&log).push((name.to_string(), args.len()));
+                Ok(Value::Number(1.0))
+            })
+            .build();
+
+        runtime.eval_str("query.f(1, 2) + query.g").unwrap();
+
+        assert_eq!(
+            vec![("f".to_string(), 2), ("g".to_string(), 0)],
+            *crate::lock(&calls)
+        );
+    }
+
+    #[test]
+    fn optimise() {
+        let runtime = Runtime::builder()
+            .constant("pi", 3.0)
+            .optimise(true)
+            .build();
+
+        assert_eq!(compile("6").unwrap(), runtime.compile("pi * 2").unwrap());
+    }
+
+    #[test]
+    fn eval_with() {
+        let runtime = Runtime::builder().alias("v", "variable").build();
+
+        let mut entity = HashMap::new();
+        entity.insert("variable".to_string(), Value::Struct(HashMap::new()));
+
+        let block = runtime.compile("v.x = 3; return v.x * 2;").unwrap();
+
+        assert_eq!(
+            Value::Number(6.0),
+            runtime.eval_with(&block, &mut entity).unwrap()
+        );
+        assert!(runtime.variables().is_empty());
+    }
+
+    #[test]
+    fn eval_linked() {
+        let mut schema = Schema::new();
+        let x = schema.variable("x");
+        let block = link(compile("x = 1; return x + 1;").unwrap(), &schema).unwrap();
+        let mut slots = vec![Value::Null; schema.len()];
+
+        let runtime = Runtime::builder()
+            .limits(Limits {
+                max_steps: Some(3),
+                ..Default::default()
+            })
+            .build();
+        assert!(matches!(
+            runtime.eval_linked(&block, &mut slots),
+            Err(MolangError::LimitExceeded(Limit::Steps))
+        ));
+
+        let runtime = Runtime::default();
+        assert_eq!(
+            Value::Number(2.0),
+            runtime.eval_linked(&block, &mut slots).unwrap()
+        );
+        assert_eq!(Value::Number(1.0), slots[x]);
+
+        runtime.cancel_handle().cancel();
+        assert!(matches!(
+            runtime.eval_linked(&block, &mut slots),
+            Err(MolangError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn compile_error() {
+        assert!(matches!(
+            Runtime::default().eval_str("1 +"),
+            Err(MolangError::CompileError(_))
+        ));
+        for empty in ["", " ", "// nothing"] {
+            assert_eq!(Err(CompileError::IncompleteExpression), compile(empty));
+        }
+    }
+
+    #[test]
+    fn eval_batch() {
+        let runtime = Runtime::builder()
+            .constant("pi", 3.0)
+            .alias("v", "variable")
+            .build();
+
+        let mut particles: Vec<HashMap<String, Value>> = (0..100)
+            .map(|i| {
+                HashMap::from([(
+                    "variable".to_string(),
+                    Value::Struct(HashMap::from([(
+                        "x".to_string(),
+                        Value::Number(i as Number),
+                    )])),
+                )])
+            })
+            .collect();
+        let mut out = vec![Value::Null; 100];
+
+        let block = runtime.compile("v.y = v.x * pi; return v.y + 1;").unwrap();
+
+        assert_eq!(
+            100,
+            runtime
+                .eval_batch(&block, &mut particles, &mut out)
+                .unwrap()
+        );
+        assert_eq!(Value::Number(298.0), out[99]);
+        assert_eq!(
+            Value::Number(297.0),
+            particles[99].get_path("variable", &["y"]).unwrap()
+        );
+
+        let mut short = vec![Value::Null; 2];
+        assert_eq!(
+            2,
+            runtime
+                .eval_batch(&block, &mut particles, &mut short)
+                .unwrap()
+        );
+
+        // Folding only happens when the runtime optimises.
+        let limited = |optimise| {
+            Runtime::builder()
+                .constant("pi", 3.0)
+                .limits(Limits {
+                    max_steps: Some(1),
+                    ..Default::default()
+                })
+                .optimise(optimise)
+                .build()
+        };
+        let block = compile("pi * 2").unwrap();
+        let mut contexts = [HashMap::new()];
+        assert!(matches!(
+            limited(false).eval_batch(&block, &mut contexts, &mut short),
+            Err(MolangError::LimitExceeded(Limit::Steps))
+        ));
+        assert_eq!(
+            1,
+            limited(true)
+                .eval_batch(&block, &mut contexts, &mut short)
+                .unwrap()
+        );
+        assert_eq!(Value::Number(6.0), short[0]);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn eval_parallel() {
+        let runtime = Runtime::builder()
+            .alias("v", "variable")
+            .function("explode", |_| panic!("boom"))
+            .build();
+
+        let double = runtime.compile("v.x = v.x * 2; return v.x;").unwrap();
+        let missing = runtime.compile("v.x + t.missing").unwrap();
+        let panics = runtime.compile("v.x + explode()").unwrap();
+
+        let mut jobs: Vec<_> = (0..100)
+            .map(|i| {
+                let variables = HashMap::from([(
+                    "variable".to_string(),
+                    Value::Struct(HashMap::from([(
+                        "x".to_string(),
+                        Value::Number(i as Number),
+                    )])),
+                )]);
+                let block = match i {
+                    50 => &missing,
+                    70 => &panics,
+                    _ => &double,
+                };
+                (block, variables)
+            })
+            .collect();
+
+        let results = runtime.eval_parallel(&mut jobs);
+
+        assert_eq!(100, results.len());
+        assert!(matches!(results[50], Err(MolangError::VariableNotFound(_))));
+        assert!(matches!(&results[70], Err(MolangError::Panicked(m)) if m == "boom"));
+        for (i, result) in results
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| ![50, 70].contains(i))
+        {
+            assert_eq!(&Value::Number((i * 2) as Number), result.as_ref().unwrap());
+            assert_eq!(
+                Value::Number((i * 2) as Number),
+                jobs[i].1.get_path("variable", &["x"]).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    hash::Hash,
+    ops::Deref,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::sync::{lock, Lock, Pointer};
+
+/// An identifier in the AST, cheap to clone and compare.
+///
+/// A symbol from an [`Interner`] has an id unique to it, and compares and
+/// hashes by that id alone, so names interned by the same runtime compare in
+/// constant time. Symbols made any other way, such as by [`crate::compile`]
+/// or once an interner is full, have no id and compare by text like `str`.
+/// The two kinds are never equal to each other, nor are symbols from
+/// different interners; compare [`Symbol::as_str`] to match text.
+#[derive(Clone)]
+pub struct Symbol {
+    id: Option<SymbolId>,
+    text: Pointer<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SymbolId {
+    interner: u32,
+    index: u32,
+}
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Where this symbol sits in its interner's table, counting from 0 in the
+    /// order names were interned, or `None` if it was not interned.
+    pub fn id(&self) -> Option<u32> {
+        self.id.map(|id| id.index)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.id, other.id) {
+            (None, None) => self.text == other.text,
+            (id, other) => id == other,
+        }
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.text == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.text == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self.id {
+            Some(id) => id.hash(state),
+            None => self.text.hash(state),
+        }
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.text, f)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol {
+            id: None,
+            text: s.into(),
+        }
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Symbol {
+            id: None,
+            text: s.into(),
+        }
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(s: Symbol) -> Self {
+        s.text.to_string()
+    }
+}
+
+/// The most names an [`Interner`] keeps by default.
+pub const DEFAULT_MAX_SYMBOLS: usize = 1 << 16;
+
+/// Gives each interner its own ids.
+static INTERNERS: AtomicU32 = AtomicU32::new(0);
+
+/// The symbol table identifiers are interned into while compiling, so each
+/// name is stored once however many blocks use it and compares by id. String
+/// literals are not interned, and once the table holds its maximum, new names
+/// get symbols without an id, so untrusted scripts cannot grow it without
+/// bound.
+///
+/// Each [`crate::Runtime`] has one. Hosts may intern names up front, e.g. the
+/// queries they answer, to compare them with the names in scripts by id.
+pub struct Interner {
+    id: u32,
+    symbols: Lock<HashMap<Pointer<str>, Symbol>>,
+    max_symbols: usize,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::with_max_symbols(DEFAULT_MAX_SYMBOLS)
+    }
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An interner which keeps no more than `max_symbols` names.
+    pub fn with_max_symbols(max_symbols: usize) -> Self {
+        Self {
+            id: INTERNERS.fetch_add(1, Ordering::Relaxed),
+            symbols: Lock::default(),
+            // Ids are `u32`.
+            max_symbols: max_symbols.min(u32::MAX as usize),
+        }
+    }
+
+    pub fn intern(&self, s: &str) -> Symbol {
+        let mut symbols = lock(&self.symbols);
+
+        if let Some(symbol) = symbols.get(s) {
+            return symbol.clone();
+        }
+        if symbols.len() >= self.max_symbols {
+            return Symbol::from(s);
+        }
+
+        let symbol = Symbol {
+            id: Some(SymbolId {
+                interner: self.id,
+                index: symbols.len() as u32,
+            }),
+            text: s.into(),
+        };
+        symbols.insert(symbol.text.clone(), symbol.clone());
+        symbol
+    }
+
+    /// The symbol for `s` if it has been interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        lock(&self.symbols).get(s).cloned()
+    }
+
+    /// Every interned symbol, in the order of their ids.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = lock(&self.symbols).values().cloned().collect();
+        symbols.sort_by_key(|symbol| symbol.id());
+        symbols
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.symbols).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        lock(&self.symbols).is_empty()
+    }
+}
+
+impl Debug for Interner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.symbols()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compile, sync::Pointer, Runtime, Value};
+
+    use super::{Interner, Symbol};
+
+    #[test]
+    fn interned() {
+        let interner = Interner::new();
+        let a = interner.intern("anim_time");
+        let b = interner.intern("anim_time");
+
+        assert_eq!(a, b);
+        assert_eq!(Some(0), a.id());
+        assert_eq!(Some(1), interner.intern("other").id());
+        assert!(Pointer::ptr_eq(&a.text, &b.text));
+        assert_eq!(a, "anim_time");
+        assert_eq!(
+            vec![a.clone(), interner.intern("other")],
+            interner.symbols()
+        );
+        assert_eq!(None, interner.get("missing"));
+
+        // Only symbols from the same interner are equal.
+        assert_ne!(Interner::new().intern("anim_time"), a);
+        assert_ne!(Symbol::from("anim_time"), a);
+        assert_eq!(Symbol::from("anim_time"), Symbol::from("anim_time"));
+    }
+
+    #[test]
+    fn shared_with_runtime() {
+        let runtime = Runtime::default();
+        let name = runtime.interner().intern("speed");
+
+        runtime.compile("v.speed = \"fast\"").unwrap();
+
+        assert_eq!(Some(name), runtime.interner().get("speed"));
+        assert!(runtime.interner().get("fast").is_none());
+        assert!(compile("v.speed").is_ok());
+    }
+
+    #[test]
+    fn literals_shared() {
+        let mut runtime = Runtime::default();
+        let block = runtime.compile("\"fast\"").unwrap();
+
+        let (Value::String(a), Value::String(b)) =
+            (runtime.eval(&block).unwrap(), runtime.eval(&block).unwrap())
+        else {
+            panic!("expected strings");
+        };
+        assert_eq!("fast", &*a);
+        assert!(Pointer::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn literals_not_interned() {
+        let runtime = Runtime::default();
+
+        for i in 0..1000 {
+            runtime.compile(&format!("v.x = \"{i}\"")).unwrap();
+        }
+
+        assert_eq!(2, runtime.interner().len());
+    }
+
+    #[test]
+    fn bounded() {
+        let interner = Interner::with_max_symbols(2);
+
+        for name in ["a", "b", "c", "a"] {
+            assert_eq!(name, interner.intern(name).as_str());
+        }
+
+        assert_eq!(2, interner.len());
+        assert_eq!(None, interner.get("c"));
+        // Names past the maximum still compare by text.
+        assert_eq!(None, interner.intern("c").id());
+        assert_eq!(interner.intern("c"), interner.intern("c"));
+    }
+}
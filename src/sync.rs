@@ -0,0 +1,108 @@
+//! Shared pointers and locks, which become `Arc` and `Mutex` with the `sync`
+//! feature so values and blocks can cross threads.
+
+#[cfg(not(feature = "sync"))]
+mod imp {
+    use std::cell::{RefCell, RefMut};
+
+    pub type Pointer<T> = std::rc::Rc<T>;
+    pub type Lock<T> = RefCell<T>;
+
+    pub fn lock<T: ?Sized>(lock: &Lock<T>) -> RefMut<'_, T> {
+        lock.borrow_mut()
+    }
+
+    /// `Send` with the `sync` feature, otherwise implemented by everything.
+    pub trait MaybeSend {}
+    impl<T: ?Sized> MaybeSend for T {}
+}
+
+#[cfg(feature = "sync")]
+mod imp {
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    pub type Pointer<T> = std::sync::Arc<T>;
+    pub type Lock<T> = Mutex<T>;
+
+    /// Locks, ignoring poisoning: a panicking host function leaves its own state
+    /// as it would without the lock.
+    pub fn lock<T: ?Sized>(lock: &Lock<T>) -> MutexGuard<'_, T> {
+        lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// `Send` with the `sync` feature, otherwise implemented by everything.
+    pub trait MaybeSend: Send {}
+    impl<T: Send + ?Sized> MaybeSend for T {}
+}
+
+pub use imp::{lock, Lock, MaybeSend, Pointer};
+
+/// How externals and functions are held in a [`crate::Value`]:
+/// `Rc<RefCell<T>>`, or `Arc<Mutex<T>>` with the `sync` feature.
+pub type Shared<T> = Pointer<Lock<T>>;
+
+pub fn shared<T>(value: T) -> Shared<T> {
+    Pointer::new(Lock::new(value))
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use crate::{Block, Runtime, Value};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<Value>();
+        assert_send_sync::<Block>();
+        assert_send_sync::<Runtime>();
+    }
+
+    #[test]
+    fn threads() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let runtime = Runtime::builder()
+            .function("count", |_| {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                Ok(Value::Null)
+            })
+            .alias("v", "variable")
+            .build();
+        let block = runtime.compile("count(); return v.x * 2;").unwrap();
+
+        let results: Vec<Value> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    let (runtime, block) = (&runtime, &block);
+                    s.spawn(move || {
+                        let mut variables = HashMap::from([(
+                            "variable".to_string(),
+                            Value::Struct(HashMap::from([(
+                                "x".to_string(),
+                                Value::Number(i as _),
+                            )])),
+                        )]);
+                        runtime.eval_with(block, &mut variables).unwrap()
+                    })
+                })
+                .collect();
+
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(
+            (0..4)
+                .map(|i| Value::Number((i * 2) as _))
+                .collect::<Vec<_>>(),
+            results
+        );
+        assert_eq!(4, CALLS.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,240 @@
+use std::ops::Range;
+
+use crate::data::Operator;
+
+use super::TokeniseError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+    Number,
+    String,
+    Identifier,
+    Operator(Operator),
+    OpenBracket,
+    CloseBracket,
+    OpenIndex,
+    CloseIndex,
+    OpenBrace,
+    CloseBrace,
+    Dot,
+    Comma,
+    Semicolon,
+}
+
+impl SourceKind {
+    /// Whitespace and comments, which the compiler skips.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            Self::Whitespace | Self::LineComment | Self::BlockComment
+        )
+    }
+}
+
+/// A token and the exact source it came from, including whitespace and
+/// comments, so the tokens of a script join back into it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceToken<'a> {
+    pub kind: SourceKind,
+    pub text: &'a str,
+    pub span: Range<usize>,
+}
+
+/// The length of the comment at the start of `rest`, if there is one.
+pub(crate) fn comment(rest: &str) -> Option<Result<(SourceKind, usize), TokeniseError>> {
+    if rest.starts_with("//") {
+        let len = rest.find('\n').unwrap_or(rest.len());
+        return Some(Ok((SourceKind::LineComment, len)));
+    }
+
+    let body = rest.strip_prefix("/*")?;
+    Some(match body.find("*/") {
+        Some(len) => Ok((SourceKind::BlockComment, len + 4)),
+        None => Err(TokeniseError::Expectation {
+            found: "EOF".to_string(),
+            expected: "*/".to_string(),
+        }),
+    })
+}
+
+/// Splits `input` into tokens without losing anything, for tools which
+/// rewrite source rather than run it. Accepts the same characters as the
+/// compiler but does not check brackets match.
+pub fn lex(input: &str) -> Result<Vec<SourceToken<'_>>, TokeniseError> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = input[pos..].chars().next() {
+        let rest = &input[pos..];
+
+        // Names after a dot may start with a digit or be `return`.
+        let after_dot = tokens
+            .last()
+            .is_some_and(|t: &SourceToken| t.kind == SourceKind::Dot);
+
+        let (kind, len) = if after_dot && (c.is_alphanumeric() || c == '_') {
+            (SourceKind::Identifier, identifier(rest))
+        } else if let Some(comment) = comment(rest) {
+            comment?
+        } else if c.is_whitespace() {
+            let len = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            (SourceKind::Whitespace, len)
+        } else {
+            match c {
+                '0'..='9' => (SourceKind::Number, number(rest)?),
+                '"' => match rest[1..].find('"') {
+                    Some(len) => (SourceKind::String, len + 2),
+                    None => {
+                        return Err(TokeniseError::Expectation {
+                            found: "EOF".to_string(),
+                            expected: "\"".to_string(),
+                        })
+                    }
+                },
+                '(' => (SourceKind::OpenBracket, 1),
+                ')' => (SourceKind::CloseBracket, 1),
+                '[' => (SourceKind::OpenIndex, 1),
+                ']' => (SourceKind::CloseIndex, 1),
+                '{' => (SourceKind::OpenBrace, 1),
+                '}' => (SourceKind::CloseBrace, 1),
+                '.' => (SourceKind::Dot, 1),
+                ',' => (SourceKind::Comma, 1),
+                ';' => (SourceKind::Semicolon, 1),
+                '*' => (SourceKind::Operator(Operator::Multiply), 1),
+                '/' => (SourceKind::Operator(Operator::Divide), 1),
+                '+' => (SourceKind::Operator(Operator::Add), 1),
+                '-' => (SourceKind::Operator(Operator::Subtract), 1),
+                ':' => (SourceKind::Operator(Operator::Colon), 1),
+                '!' => (SourceKind::Operator(Operator::Not), 1),
+                '?' if rest.starts_with("??") => {
+                    (SourceKind::Operator(Operator::NullishCoalescing), 2)
+                }
+                '?' => (SourceKind::Operator(Operator::Conditional), 1),
+                '=' if rest.starts_with("==") => (SourceKind::Operator(Operator::Equality), 2),
+                '=' => (SourceKind::Operator(Operator::Assignment), 1),
+                c if c.is_alphabetic() => {
+                    let len = identifier(rest);
+                    match &rest[..len] {
+                        "return" => (SourceKind::Operator(Operator::Return), len),
+                        _ => (SourceKind::Identifier, len),
+                    }
+                }
+                c => {
+                    return Err(TokeniseError::Expectation {
+                        found: c.to_string(),
+                        expected: "anything else".to_string(),
+                    })
+                }
+            }
+        };
+
+        tokens.push(SourceToken {
+            kind,
+            text: &rest[..len],
+            span: pos..pos + len,
+        });
+        pos += len;
+    }
+
+    Ok(tokens)
+}
+
+/// The span of each statement in `tokens`, from its first token to its `;`,
+/// in the order of a compiled block's statements.
+pub(crate) fn statement_spans(tokens: &[SourceToken]) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut depth: usize = 0;
+    let mut start = None;
+
+    for token in tokens.iter().filter(|t| !t.kind.is_trivia()) {
+        let statement_start = *start.get_or_insert(token.span.start);
+
+        match token.kind {
+            SourceKind::OpenBracket | SourceKind::OpenIndex | SourceKind::OpenBrace => depth += 1,
+            SourceKind::CloseBracket | SourceKind::CloseIndex | SourceKind::CloseBrace => {
+                depth = depth.saturating_sub(1)
+            }
+            SourceKind::Semicolon if depth == 0 => {
+                spans.push(statement_start..token.span.end);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let (Some(start), Some(last)) = (start, tokens.iter().rfind(|t| !t.kind.is_trivia())) {
+        spans.push(start..last.span.end);
+    }
+
+    spans
+}
+
+fn identifier(rest: &str) -> usize {
+    rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len())
+}
+
+fn number(rest: &str) -> Result<usize, TokeniseError> {
+    let mut point = false;
+
+    for (i, byte) in rest.bytes().enumerate() {
+        match byte {
+            b'0'..=b'9' | b'_' => {}
+            b'.' if point => {
+                return Err(TokeniseError::Expectation {
+                    found: ".".to_string(),
+                    expected: "a digit".to_string(),
+                })
+            }
+            b'.' => point = true,
+            _ => return Ok(i),
+        }
+    }
+
+    Ok(rest.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lex, statement_spans, SourceKind};
+
+    #[test]
+    fn lossless() {
+        let source = "v.x = 1.5; // set x\n/* then */ return v.x ?? \"a b\";";
+        let tokens = lex(source).unwrap();
+
+        assert_eq!(source, tokens.iter().map(|t| t.text).collect::<String>());
+        assert_eq!(
+            vec![SourceKind::LineComment, SourceKind::BlockComment],
+            tokens
+                .iter()
+                .map(|t| t.kind)
+                .filter(|k| matches!(k, SourceKind::LineComment | SourceKind::BlockComment))
+                .collect::<Vec<_>>()
+        );
+        assert!(tokens.iter().all(|t| &source[t.span.clone()] == t.text));
+    }
+
+    #[test]
+    fn statements() {
+        let source = " t.a = f(1; 2); // x\n{ b; } ;return t.a ";
+        let spans = statement_spans(&lex(source).unwrap());
+
+        assert_eq!(
+            vec!["t.a = f(1; 2);", "{ b; } ;", "return t.a"],
+            spans.iter().map(|s| &source[s.clone()]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unterminated() {
+        for source in ["\"abc", "1 /* a", "1.2.3"] {
+            assert!(lex(source).is_err(), "{source}");
+        }
+    }
+}
@@ -1,20 +1,32 @@
-use std::{default::Default, fmt::Display};
+use std::{fmt::Display, ops::Range};
 use thiserror::Error;
 
 use crate::{
     blockiser::{blockise, Block},
     data::Operator,
-    state::{SequenceAction, State},
+    CompileError, Interner, Number, Symbol,
 };
 
+mod lossless;
+
+pub(crate) use lossless::{comment, statement_spans};
+pub use lossless::{lex, SourceKind, SourceToken};
+
+/// A token, or part of an access, and the bytes of the source it spans.
+#[derive(Debug, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Range<usize>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Token {
-    Number(f32),
+    Number(Number),
     String(String),
     Operator(Operator),
     OpenBracket,
     CloseBracket,
-    Access(Vec<Access>),
+    Access(Vec<Spanned<Access>>),
     Comma,
     Semicolon,
     Block(Block),
@@ -22,555 +34,489 @@ pub enum Token {
 
 #[derive(Debug, PartialEq)]
 pub enum Access {
-    Name(String),
-    Index(Vec<Token>),
-    Call(Vec<Token>),
+    Name(Symbol),
+    Index(Vec<Spanned<Token>>),
+    Call(Vec<Spanned<Token>>),
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum TokeniseError {
-    Expectation { found: String, expected: String },
+    Expectation {
+        found: String,
+        expected: String,
+    },
+    /// The statements inside `{}` do not form a valid block.
+    Block(Box<CompileError>),
 }
 
 impl Display for TokeniseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!("{:?}", self)).unwrap();
-        Ok(())
+        write!(f, "{self:?}")
     }
 }
 
-struct NormalState {}
-impl State<char, Token, TokeniseError> for NormalState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Token>,
-            Option<Box<dyn State<char, Token, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        match c {
-            Some(c) if c.is_numeric() => Ok((
-                None,
-                Some(Box::new(NumberState {
-                    ..Default::default()
-                })),
-                SequenceAction::Hold,
-            )),
-
-            Some(c) if c.is_alphabetic() => Ok((
-                None,
-                Some(Box::new(AccessTokenState {
-                    state: Box::new(IdentifierState {
-                        ..Default::default()
-                    }),
-                    accesses: Vec::new(),
-                })),
-                SequenceAction::Hold,
-            )),
-
-            Some(c) if c.is_whitespace() => Ok((None, None, SequenceAction::Advance)),
-
-            Some(',') => Ok((Some(Token::Comma), None, SequenceAction::Advance)),
-            Some('*') => Ok((
-                Some(Token::Operator(Operator::Multiply)),
-                None,
-                SequenceAction::Advance,
-            )),
-            Some('/') => Ok((
-                Some(Token::Operator(Operator::Divide)),
-                None,
-                SequenceAction::Advance,
-            )),
-            Some('+') => Ok((
-                Some(Token::Operator(Operator::Add)),
-                None,
-                SequenceAction::Advance,
-            )),
-            Some(':') => Ok((
-                Some(Token::Operator(Operator::Colon)),
-                None,
-                SequenceAction::Advance,
-            )),
-            Some('-') => Ok((
-                Some(Token::Operator(Operator::Subtract)),
-                None,
-                SequenceAction::Advance,
-            )),
-            Some('!') => Ok((
-                Some(Token::Operator(Operator::Not)),
-                None,
-                SequenceAction::Advance,
-            )),
-            Some(';') => Ok((Some(Token::Semicolon), None, SequenceAction::Advance)),
-
-            Some('(') => Ok((Some(Token::OpenBracket), None, SequenceAction::Advance)),
-            Some(')') => Ok((Some(Token::CloseBracket), None, SequenceAction::Advance)),
-
-            Some('?') => Ok((
-                None,
-                Some(Box::new(DoubleState {
-                    target: '?',
-                    result_single: Some(Token::Operator(Operator::Conditional)),
-                    result_double: Some(Token::Operator(Operator::NullishCoalescing)),
-                })),
-                SequenceAction::Advance,
-            )),
-
-            Some('=') => Ok((
-                None,
-                Some(Box::new(DoubleState {
-                    target: '=',
-                    result_single: Some(Token::Operator(Operator::Assignment)),
-                    result_double: Some(Token::Operator(Operator::Equality)),
-                })),
-                SequenceAction::Advance,
-            )),
-
-            Some('{') => Ok((
-                None,
-                Some(Box::new(BlockState {
-                    ..Default::default()
-                })),
-                SequenceAction::Advance,
-            )),
-
-            Some('"') => Ok((
-                None,
-                Some(Box::new(StringState {
-                    ..Default::default()
-                })),
-                SequenceAction::Advance,
-            )),
-
-            Some(c) => Err(TokeniseError::Expectation {
-                found: c.to_string(),
-                expected: "anything else".to_string(),
-            }),
-            None => Ok((None, None, SequenceAction::Done)),
+/// A single pass over the bytes of a script. Brackets, indexes and blocks are
+/// lexed recursively as they are reached rather than captured and re-lexed.
+struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Where identifiers are interned, if anywhere.
+    interner: Option<&'a Interner>,
+    /// The limit `{}` blocks are compiled with.
+    max_depth: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
         }
+        found
     }
-}
 
-#[derive(Default)]
-struct StringState {
-    string: String,
-}
-impl State<char, Token, TokeniseError> for StringState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Token>,
-            Option<Box<dyn State<char, Token, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        match c {
-            Some('"') => Ok((
-                Some(Token::String(self.string.clone())),
-                Some(Box::new(NormalState {})),
-                SequenceAction::Advance,
-            )),
-            Some(c) => {
-                self.string.push(c);
-                Ok((None, None, SequenceAction::Advance))
+    /// Skips whitespace and comments.
+    fn skip_whitespace(&mut self) -> Result<(), TokeniseError> {
+        while let Some(c) = self.peek_char() {
+            if let Some(comment) = comment(&self.input[self.pos..]) {
+                self.pos += comment?.1;
+            } else if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else {
+                break;
             }
-            None => Err(TokeniseError::Expectation {
-                found: "EOF".to_string(),
-                expected: "\"".to_string(),
-            }),
         }
+        Ok(())
     }
-}
 
-#[derive(Default)]
-struct NumberState {
-    point: bool,
-    string: String,
-}
-impl State<char, Token, TokeniseError> for NumberState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Token>,
-            Option<Box<dyn State<char, Token, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        match c {
-            Some(c) if c.is_numeric() => {
-                self.string.push(c);
-                Ok((None, None, SequenceAction::Advance))
+    /// Lexes until `close`, which is consumed, or the end of input if `None`.
+    fn tokens(&mut self, close: Option<u8>) -> Result<Vec<Spanned<Token>>, TokeniseError> {
+        let mut tokens = Vec::new();
+        let mut open_brackets: usize = 0;
+
+        loop {
+            self.skip_whitespace()?;
+
+            let Some(byte) = self.peek() else {
+                return match close {
+                    None => Ok(tokens),
+                    Some(close) => Err(TokeniseError::Expectation {
+                        found: "EOF".to_string(),
+                        expected: (close as char).to_string(),
+                    }),
+                };
+            };
+
+            if Some(byte) == close && !(byte == b')' && open_brackets > 0) {
+                self.pos += 1;
+                return Ok(tokens);
             }
-            Some('.') => {
-                if self.point {
+
+            let start = self.pos;
+            let token = match byte {
+                b'0'..=b'9' => self.number()?,
+                b'"' => self.string()?,
+                b'(' => {
+                    self.pos += 1;
+                    open_brackets += 1;
+                    Token::OpenBracket
+                }
+                b')' => {
+                    self.pos += 1;
+                    open_brackets = open_brackets.saturating_sub(1);
+                    Token::CloseBracket
+                }
+                b'{' => {
+                    self.pos += 1;
+                    let inner = self.tokens(Some(b'}'))?;
+                    Token::Block(
+                        blockise(inner, self.max_depth, None)
+                            .map_err(|e| TokeniseError::Block(Box::new(e)))?,
+                    )
+                }
+                b',' => self.single(Token::Comma),
+                b';' => self.single(Token::Semicolon),
+                b'*' => self.single(Token::Operator(Operator::Multiply)),
+                b'/' => self.single(Token::Operator(Operator::Divide)),
+                b'+' => self.single(Token::Operator(Operator::Add)),
+                b'-' => self.single(Token::Operator(Operator::Subtract)),
+                b':' => self.single(Token::Operator(Operator::Colon)),
+                b'!' => self.single(Token::Operator(Operator::Not)),
+                b'?' => self.double(b'?', Operator::Conditional, Operator::NullishCoalescing),
+                b'=' => self.double(b'=', Operator::Assignment, Operator::Equality),
+                _ => match self.peek_char() {
+                    Some(c) if c.is_alphabetic() => self.access()?,
+                    c => {
+                        return Err(TokeniseError::Expectation {
+                            found: c.map(String::from).unwrap_or_default(),
+                            expected: "anything else".to_string(),
+                        })
+                    }
+                },
+            };
+
+            tokens.push(Spanned {
+                value: token,
+                span: start..self.pos,
+            });
+        }
+    }
+
+    fn single(&mut self, token: Token) -> Token {
+        self.pos += 1;
+        token
+    }
+
+    fn double(&mut self, target: u8, single: Operator, double: Operator) -> Token {
+        self.pos += 1;
+        Token::Operator(if self.eat(target) { double } else { single })
+    }
+
+    fn number(&mut self) -> Result<Token, TokeniseError> {
+        let start = self.pos;
+        let mut point = false;
+
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'0'..=b'9' | b'_' => {}
+                b'.' if point => {
                     return Err(TokeniseError::Expectation {
                         found: ".".to_string(),
                         expected: "a digit".to_string(),
-                    });
+                    })
                 }
-                self.string.push('.');
-                self.point = true;
-                Ok((None, None, SequenceAction::Advance))
+                b'.' => point = true,
+                _ => break,
             }
-            Some('_') => Ok((None, None, SequenceAction::Advance)),
-            None => Ok((
-                Some(Token::Number(self.string.parse().unwrap())),
-                None,
-                SequenceAction::Done,
-            )),
-            _ => Ok((
-                Some(Token::Number(self.string.parse().unwrap())),
-                Some(Box::new(NormalState {})),
-                SequenceAction::Hold,
-            )),
+            self.pos += 1;
         }
+
+        let digits = self.input[start..self.pos].replace('_', "");
+
+        digits
+            .parse()
+            .map(Token::Number)
+            .map_err(|_| TokeniseError::Expectation {
+                found: digits,
+                expected: "a number".to_string(),
+            })
     }
-}
 
-#[derive(Default)]
-struct BlockState {
-    chars: String,
-    open: u32,
-}
-impl State<char, Token, TokeniseError> for BlockState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Token>,
-            Option<Box<dyn State<char, Token, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        match c {
-            Some('}') if self.open == 0 => Ok((
-                Some(Token::Block(blockise(tokenise(&self.chars)?).unwrap())),
-                Some(Box::new(NormalState {})),
-                SequenceAction::Advance,
-            )),
-            Some('}') => {
-                self.chars.push('}');
-                self.open -= 1;
-                Ok((None, None, SequenceAction::Advance))
-            }
-            Some('{') => {
-                self.chars.push('{');
-                self.open += 1;
-                Ok((None, None, SequenceAction::Advance))
-            }
-            Some(c) => {
-                self.chars.push(c);
-                Ok((None, None, SequenceAction::Advance))
+    fn string(&mut self) -> Result<Token, TokeniseError> {
+        self.pos += 1;
+
+        match self.input[self.pos..].find('"') {
+            Some(len) => {
+                // Literals are not interned, so scripts cannot grow the table with data.
+                let string = self.input[self.pos..self.pos + len].to_string();
+                self.pos += len + 1;
+                Ok(Token::String(string))
             }
             None => Err(TokeniseError::Expectation {
                 found: "EOF".to_string(),
-                expected: "}".to_string(),
+                expected: "\"".to_string(),
             }),
         }
     }
-}
 
-struct AccessTokenState {
-    state: Box<dyn State<char, Access, TokeniseError>>,
-    accesses: Vec<Access>,
-}
-impl State<char, Token, TokeniseError> for AccessTokenState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Token>,
-            Option<Box<dyn State<char, Token, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        let (access, new_state, action) = self.state.handle(c)?;
-
-        match access {
-            Some(access) => self.accesses.push(access),
-            None => {}
+    fn name(&self, name: &str) -> Symbol {
+        match self.interner {
+            Some(interner) => interner.intern(name),
+            None => Symbol::from(name),
         }
+    }
 
-        match new_state {
-            Some(new_state) => {
-                self.state = new_state;
-            }
-            None => {}
-        }
+    fn identifier(&mut self) -> &'a str {
+        let start = self.pos;
 
-        match action {
-            SequenceAction::Done => {
-                if self.accesses[0] == Access::Name("return".to_string()) {
-                    // perf: create this string once
-                    return Ok((
-                        Some(Token::Operator(Operator::Return)),
-                        Some(Box::new(NormalState {})),
-                        SequenceAction::Hold,
-                    ));
-                }
-                Ok((
-                    Some(Token::Access(std::mem::take(&mut self.accesses))),
-                    Some(Box::new(NormalState {})),
-                    SequenceAction::Hold,
-                ))
+        while let Some(c) = self.peek_char() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
             }
-            SequenceAction::Advance => Ok((None, None, SequenceAction::Advance)),
-            SequenceAction::Hold => Ok((None, None, SequenceAction::Hold)),
+            self.pos += c.len_utf8();
         }
+
+        &self.input[start..self.pos]
     }
-}
 
-struct AccessState {}
-impl State<char, Access, TokeniseError> for AccessState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Access>,
-            Option<Box<dyn State<char, Access, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        match c {
-            Some(c) if c.is_whitespace() => Ok((None, None, SequenceAction::Advance)),
-            Some('.') => Ok((
-                None,
-                Some(Box::new(IdentifierState {
-                    ..Default::default()
-                })),
-                SequenceAction::Advance,
-            )),
-            Some('(') => Ok((
-                None,
-                Some(Box::new(BracketState {
-                    call: true,
-                    ..Default::default()
-                })),
-                SequenceAction::Advance,
-            )),
-            Some('[') => Ok((
-                None,
-                Some(Box::new(BracketState {
-                    call: false,
-                    ..Default::default()
-                })),
-                SequenceAction::Advance,
-            )),
-
-            _ => Ok((None, None, SequenceAction::Done)),
+    fn access(&mut self) -> Result<Token, TokeniseError> {
+        let root = self.identifier();
+
+        if root == "return" {
+            return Ok(Token::Operator(Operator::Return));
         }
-    }
-}
 
-#[derive(Default)]
-struct IdentifierState {
-    identifier: String,
-}
-impl State<char, Access, TokeniseError> for IdentifierState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Access>,
-            Option<Box<dyn State<char, Access, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        match c {
-            Some(c) if c.is_alphanumeric() || c == '_' => {
-                self.identifier.push(c);
-                Ok((None, None, SequenceAction::Advance))
-            }
-            _ => Ok((
-                Some(Access::Name(self.identifier.clone())),
-                Some(Box::new(AccessState {})),
-                SequenceAction::Hold,
-            )),
+        let mut accesses = vec![Spanned {
+            value: Access::Name(self.name(root)),
+            span: self.pos - root.len()..self.pos,
+        }];
+
+        loop {
+            let end = self.pos;
+            self.skip_whitespace()?;
+
+            let start = self.pos;
+            let access = match self.peek() {
+                Some(b'.') => {
+                    self.pos += 1;
+                    let start = self.pos;
+                    let name = self.identifier();
+                    accesses.push(Spanned {
+                        value: Access::Name(self.name(name)),
+                        span: start..self.pos,
+                    });
+                    continue;
+                }
+                Some(b'(') => {
+                    self.pos += 1;
+                    Access::Call(self.tokens(Some(b')'))?)
+                }
+                Some(b'[') => {
+                    self.pos += 1;
+                    Access::Index(self.tokens(Some(b']'))?)
+                }
+                _ => {
+                    // Leave what follows the access for the next token.
+                    self.pos = end;
+                    return Ok(Token::Access(accesses));
+                }
+            };
+
+            accesses.push(Spanned {
+                value: access,
+                span: start..self.pos,
+            });
         }
     }
 }
 
-#[derive(Default)]
-struct BracketState {
-    call: bool,
-    inner: String,
-    open_brackets: i32,
-}
-impl State<char, Access, TokeniseError> for BracketState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Access>,
-            Option<Box<dyn State<char, Access, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
-        let open = if self.call { '(' } else { '[' };
-        let close = if self.call { ')' } else { ']' };
-        match c {
-            Some(c) if c == open => {
-                self.open_brackets += 1;
-                self.inner.push(c);
-                Ok((None, None, SequenceAction::Advance))
+/// The deepest nesting of brackets and braces in `input`, outside of strings
+/// and comments.
+pub fn nesting_depth(input: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut deepest = 0;
+    let mut in_string = false;
+    let mut pos = 0;
+
+    while let Some(c) = input[pos..].chars().next() {
+        if !in_string {
+            if let Some(comment) = comment(&input[pos..]) {
+                pos += comment.map_or(input.len() - pos, |(_, len)| len);
+                continue;
             }
-            Some(c) if c == close && self.open_brackets != 0 => {
-                self.open_brackets -= 1;
-                self.inner.push(c);
-                Ok((None, None, SequenceAction::Advance))
-            }
-            Some(c) if c == close && self.open_brackets == 0 => {
-                self.open_brackets += 1;
-                let acc = if self.call {
-                    Access::Call(tokenise(&self.inner)?)
-                } else {
-                    Access::Index(tokenise(&self.inner)?)
-                };
-                Ok((
-                    Some(acc),
-                    Some(Box::new(AccessState {})),
-                    SequenceAction::Advance,
-                ))
-            }
-            Some(c) => {
-                self.inner.push(c);
-                Ok((None, None, SequenceAction::Advance))
-            }
-            None => Err(TokeniseError::Expectation {
-                found: "EOF".to_string(),
-                expected: ")".to_string(),
-            }),
         }
-    }
-}
 
-struct DoubleState {
-    target: char,
-    result_single: Option<Token>,
-    result_double: Option<Token>,
-}
-impl State<char, Token, TokeniseError> for DoubleState {
-    fn handle(
-        &mut self,
-        c: Option<char>,
-    ) -> Result<
-        (
-            Option<Token>,
-            Option<Box<dyn State<char, Token, TokeniseError>>>,
-            SequenceAction,
-        ),
-        TokeniseError,
-    > {
         match c {
-            Some(c) if c == self.target => Ok((
-                Some(self.result_double.take().unwrap()),
-                Some(Box::new(NormalState {})),
-                SequenceAction::Advance,
-            )),
-            _ => Ok((
-                Some(self.result_single.take().unwrap()),
-                Some(Box::new(NormalState {})),
-                SequenceAction::Hold,
-            )),
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            ')' | ']' | '}' if !in_string => depth = depth.saturating_sub(1),
+            _ => {}
         }
+        pos += c.len_utf8();
     }
-}
-
-pub fn tokenise(input: &str) -> Result<Vec<Token>, TokeniseError> {
-    let mut state: Box<dyn State<char, Token, TokeniseError>> = Box::new(NormalState {});
-
-    let mut i = 0;
 
-    let mut tokens = Vec::new();
+    deepest
+}
 
-    loop {
-        let (token, new_state, action) = state.handle(input.chars().nth(i))?;
-        if let Some(new_state) = new_state {
-            state = new_state;
-        }
-        if let Some(token) = token {
-            tokens.push(token);
-        }
-        match action {
-            SequenceAction::Advance => i += 1,
-            SequenceAction::Done => break,
-            SequenceAction::Hold => {}
-        }
+/// Lexes `input`, compiling any `{}` blocks no more than `max_depth` deep.
+pub fn tokenise(
+    input: &str,
+    interner: Option<&Interner>,
+    max_depth: usize,
+) -> Result<Vec<Spanned<Token>>, TokeniseError> {
+    Lexer {
+        input,
+        pos: 0,
+        interner,
+        max_depth,
     }
-
-    Ok(tokens)
+    .tokens(None)
 }
 
 #[cfg(test)]
 mod test {
-    use std::collections::VecDeque;
+    use std::{ops::Range, time::Instant};
 
     use crate::{
         data::Operator,
-        tokeniser::{tokenise, Access, Token},
+        limits::DEFAULT_MAX_DEPTH,
+        tokeniser::{nesting_depth, tokenise, Access, Spanned, Token},
     };
 
+    fn at<T>(value: T, span: Range<usize>) -> Spanned<T> {
+        Spanned { value, span }
+    }
+
     #[test]
     fn number() {
         assert_eq!(
-            VecDeque::from([Token::Number(100.0)]),
-            tokenise("100.0").unwrap()
+            vec![at(Token::Number(100.0), 0..5)],
+            tokenise("100.0", None, DEFAULT_MAX_DEPTH).unwrap()
         );
     }
 
     #[test]
     fn function() {
         assert_eq!(
-            Vec::from([Token::Access(vec![
-                Access::Name("math".to_string()),
-                Access::Name("sin".to_string()),
-                Access::Call(vec![Token::Number(1.0)])
-            ])]),
-            tokenise("math.sin(1)").unwrap()
+            vec![at(
+                Token::Access(vec![
+                    at(Access::Name("math".into()), 0..4),
+                    at(Access::Name("sin".into()), 5..8),
+                    at(Access::Call(vec![at(Token::Number(1.0), 9..10)]), 8..11)
+                ]),
+                0..11
+            )],
+            tokenise("math.sin(1)", None, DEFAULT_MAX_DEPTH).unwrap()
         );
     }
 
     #[test]
     fn multiply() {
         assert_eq!(
-            VecDeque::from([
-                Token::Number(100.0),
-                Token::Operator(Operator::Multiply),
-                Token::Number(99.0)
-            ]),
-            tokenise("100.0*99").unwrap()
+            vec![
+                at(Token::Number(100.0), 0..5),
+                at(Token::Operator(Operator::Multiply), 5..6),
+                at(Token::Number(99.0), 6..8)
+            ],
+            tokenise("100.0*99", None, DEFAULT_MAX_DEPTH).unwrap()
         );
     }
 
     #[test]
     fn divide() {
         assert_eq!(
-            VecDeque::from([
-                Token::Number(100.0),
-                Token::Operator(Operator::Divide),
-                Token::Number(99.0)
-            ]),
-            tokenise("100.0/99").unwrap()
+            vec![
+                at(Token::Number(100.0), 0..5),
+                at(Token::Operator(Operator::Divide), 5..6),
+                at(Token::Number(99.0), 6..8)
+            ],
+            tokenise("100.0/99", None, DEFAULT_MAX_DEPTH).unwrap()
+        );
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(
+            vec![at(
+                Token::Access(vec![
+                    at(Access::Name("f".into()), 0..1),
+                    at(
+                        Access::Call(vec![
+                            at(Token::OpenBracket, 2..3),
+                            at(Token::String(")]".into()), 3..7),
+                            at(Token::CloseBracket, 7..8),
+                            at(Token::Comma, 8..9),
+                            at(
+                                Token::Access(vec![
+                                    at(Access::Name("a".into()), 10..11),
+                                    at(Access::Index(vec![at(Token::Number(1.0), 12..13)]), 11..14)
+                                ]),
+                                10..14
+                            )
+                        ]),
+                        1..15
+                    )
+                ]),
+                0..15
+            )],
+            tokenise("f((\")]\"), a[1])", None, DEFAULT_MAX_DEPTH).unwrap()
+        );
+    }
+
+    #[test]
+    fn return_bracket() {
+        assert_eq!(
+            vec![
+                at(Token::Operator(Operator::Return), 0..6),
+                at(Token::OpenBracket, 7..8),
+                at(Token::Number(1.0), 8..9),
+                at(Token::CloseBracket, 9..10)
+            ],
+            tokenise("return (1)", None, DEFAULT_MAX_DEPTH).unwrap()
         );
     }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            vec![
+                at(Token::Number(1.0), 0..1),
+                at(Token::Operator(Operator::Divide), 9..10),
+                at(Token::Number(2.0), 22..23)
+            ],
+            tokenise("1 // one\n/ /* (two */ 2", None, DEFAULT_MAX_DEPTH).unwrap()
+        );
+        assert_eq!(0, nesting_depth("1 // ((\n/* [[ */"));
+    }
+
+    #[test]
+    fn access_spans() {
+        // Whitespace may separate an access from its index or call, as it
+        // could before the byte lexer, but is not part of the access.
+        assert_eq!(
+            vec![
+                at(
+                    Token::Access(vec![
+                        at(Access::Name("q".into()), 0..1),
+                        at(Access::Name("a".into()), 2..3),
+                        at(Access::Index(vec![at(Token::Number(0.0), 5..6)]), 4..7)
+                    ]),
+                    0..7
+                ),
+                at(Token::Operator(Operator::Add), 9..10),
+                at(Token::Number(1.0), 11..12)
+            ],
+            tokenise("q.a [0]  + 1", None, DEFAULT_MAX_DEPTH).unwrap()
+        );
+    }
+
+    #[test]
+    fn linear() {
+        let script = |statements: usize| -> String {
+            (0..statements)
+                .map(|i| {
+                    format!(
+                        "v.x{i} = math.max(q.a[{i}], (v.b ?? 2) * 3) == \"s\" ? {{ 1; }} : 0;\n"
+                    )
+                })
+                .collect()
+        };
+        let time = |source: &str| {
+            (0..3)
+                .map(|_| {
+                    let start = Instant::now();
+                    tokenise(source, None, DEFAULT_MAX_DEPTH).unwrap();
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+
+        // Ten times the input should take about ten times as long, where
+        // quadratic lexing would take a hundred.
+        let small = time(&script(500));
+        let large = time(&script(5_000));
+        assert!(large < small * 30, "{small:?} to {large:?}");
+    }
+
+    #[test]
+    fn unterminated() {
+        for source in ["f(1", "a[1", "{1;", "\"abc", "f(1]", "1 /* 2"] {
+            assert!(
+                tokenise(source, None, DEFAULT_MAX_DEPTH).is_err(),
+                "{source}"
+            );
+        }
+    }
 }
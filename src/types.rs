@@ -0,0 +1,627 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+};
+
+use thiserror::Error;
+
+use crate::{
+    blockiser::Block,
+    parser::{AccessExpr, Instruction},
+    Expr, Value,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Number,
+    String,
+    Struct,
+    /// An external indexed with `[]`, holding elements of one type.
+    Array(Box<Type>),
+    Null,
+    /// Not known until run time, so never a mismatch.
+    Any,
+}
+
+impl Type {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Number(_) => Type::Number,
+            Value::String(_) => Type::String,
+            Value::Struct(_) => Type::Struct,
+            Value::Null => Type::Null,
+            Value::External(_) | Value::Function(_) => Type::Any,
+        }
+    }
+
+    /// Whether a value of type `found` may be used where `self` is needed.
+    pub fn accepts(&self, found: &Type) -> bool {
+        match (self, found) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Array(expected), Type::Array(found)) => expected.accepts(found),
+            (expected, found) => expected == found,
+        }
+    }
+
+    /// The type of a value which is either `self` or `other`.
+    fn join(self, other: Type) -> Type {
+        if self == other {
+            self
+        } else {
+            Type::Any
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Number => f.write_str("Number"),
+            Type::String => f.write_str("String"),
+            Type::Struct => f.write_str("Struct"),
+            Type::Array(element) => write!(f, "Array<{element}>"),
+            Type::Null => f.write_str("Null"),
+            Type::Any => f.write_str("Any"),
+        }
+    }
+}
+
+/// A function's parameters, and the type of any further arguments it takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub variadic: Option<Type>,
+    pub returns: Type,
+}
+
+/// The types of the names a script may use, by full path such as
+/// `query.anim_time` or `math.max`. Undeclared names are [`Type::Any`].
+#[derive(Debug, Default, Clone)]
+pub struct Signatures {
+    values: HashMap<String, Type>,
+    functions: HashMap<String, Signature>,
+    /// Roots which, like the runtime's constants, cannot be assigned under.
+    constants: HashSet<String>,
+}
+
+impl Signatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A constant, which makes everything under its root read-only.
+    pub fn constant(mut self, name: &str, ty: Type) -> Self {
+        self.constants.insert(root(name).to_string());
+        self.values.insert(name.to_string(), ty);
+        self
+    }
+
+    /// A query, which is read-only as it comes from a constant provider.
+    pub fn query(self, name: &str, ty: Type) -> Self {
+        self.constant(name, ty)
+    }
+
+    /// Declares the constant `name` unless it already is.
+    pub(crate) fn constant_default(mut self, name: &str, ty: Type) -> Self {
+        self.constants.insert(root(name).to_string());
+        self.values.entry(name.to_string()).or_insert(ty);
+        self
+    }
+
+    /// A variable which assignments must keep to `ty`.
+    pub fn variable(mut self, name: &str, ty: Type) -> Self {
+        self.values.insert(name.to_string(), ty);
+        self
+    }
+
+    /// A function, or a query taking arguments.
+    pub fn function(mut self, name: &str, signature: Signature) -> Self {
+        self.functions.insert(name.to_string(), signature);
+        self
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum TypeError {
+    #[error("Expected {expected} but `{expr}` is {found}")]
+    Mismatch {
+        expected: Type,
+        found: Type,
+        expr: String,
+    },
+
+    #[error("`{function}` takes {expected} arguments but is given {found}")]
+    Arguments {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("`{path}` is constant, so cannot be assigned")]
+    Constant { path: String },
+}
+
+/// Infers the type of every expression in `block` from `signatures` and the
+/// assignments before it in the order they run, reporting each place the
+/// interpreter would fail with [`crate::MolangError::TypeError`] or a bad
+/// access. A variable which a condition may or may not assign, or gives
+/// a different type in each branch, is [`Type::Any`] after it.
+pub fn check_types(
+    block: &Block,
+    signatures: &Signatures,
+    aliases: &HashMap<String, String>,
+) -> Vec<TypeError> {
+    let mut checker = Checker {
+        signatures,
+        aliases,
+        assigned: HashMap::new(),
+        errors: Vec::new(),
+    };
+
+    for statement in &block.statements {
+        checker.expr(statement);
+    }
+
+    checker.errors
+}
+
+struct Checker<'a> {
+    signatures: &'a Signatures,
+    aliases: &'a HashMap<String, String>,
+    /// Variables assigned so far, with the type last given to them.
+    assigned: HashMap<String, Type>,
+    errors: Vec<TypeError>,
+}
+
+impl Checker<'_> {
+    fn expect(&mut self, expected: Type, expr: &Expr) -> Type {
+        let found = self.expr(expr);
+        if !expected.accepts(&found) {
+            self.errors.push(TypeError::Mismatch {
+                expected,
+                found: found.clone(),
+                expr: expr.to_string(),
+            });
+        }
+        found
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        let instruction = match expr {
+            Expr::Literal(value) => return Type::of(value),
+            Expr::Derived(instruction) => instruction.as_ref(),
+        };
+
+        match instruction {
+            Instruction::Add(left, right)
+            | Instruction::Subtract(left, right)
+            | Instruction::Multiply(left, right)
+            | Instruction::Divide(left, right) => {
+                self.expect(Type::Number, left);
+                self.expect(Type::Number, right);
+                Type::Number
+            }
+            Instruction::Not(expr) => {
+                self.expect(Type::Number, expr);
+                Type::Number
+            }
+            Instruction::Equality(left, right) => {
+                self.expr(left);
+                self.expr(right);
+                Type::Number
+            }
+            Instruction::Conditional(condition, branches) => {
+                self.expect(Type::Number, condition);
+                match branches {
+                    Expr::Derived(i) => match i.as_ref() {
+                        Instruction::Colon(if_true, if_false) => {
+                            self.either(if_true, Some(if_false))
+                        }
+                        _ => self.either(branches, None),
+                    },
+                    _ => self.either(branches, None),
+                }
+            }
+            Instruction::Colon(left, right) => {
+                self.expr(left);
+                self.expr(right);
+                Type::Any
+            }
+            Instruction::NullishCoalescing(left, right) => match self.expr(left) {
+                Type::Null => self.expr(right),
+                Type::Any => {
+                    self.either(right, None);
+                    Type::Any
+                }
+                left => {
+                    let right = self.either(right, None);
+                    left.join(right)
+                }
+            },
+            Instruction::Return(expr) => self.expr(expr),
+            Instruction::Assignment(left, right) => {
+                let ty = self.expr(right);
+
+                match self.path(left) {
+                    Some(path) if self.signatures.constants.contains(root(&path)) => {
+                        self.errors.push(TypeError::Constant { path });
+                    }
+                    Some(path) => {
+                        if let Some(declared) = self.signatures.values.get(&path) {
+                            if !declared.accepts(&ty) {
+                                self.errors.push(TypeError::Mismatch {
+                                    expected: declared.clone(),
+                                    found: ty.clone(),
+                                    expr: right.to_string(),
+                                });
+                            }
+                        }
+                        self.assigned.insert(path, ty.clone());
+                    }
+                    None => {
+                        self.expr(left);
+                    }
+                }
+
+                ty
+            }
+            Instruction::Access(accesses) => self.access(accesses),
+        }
+    }
+
+    /// Checks branches of which only one runs, each from the variables
+    /// assigned before them, leaving variables typed as either branch left
+    /// them. With no `otherwise`, nothing may run instead of `branch`.
+    fn either(&mut self, branch: &Expr, otherwise: Option<&Expr>) -> Type {
+        let before = self.assigned.clone();
+        let ty = self.expr(branch);
+        let assigned = std::mem::replace(&mut self.assigned, before);
+        let ty = match otherwise {
+            Some(otherwise) => ty.join(self.expr(otherwise)),
+            None => ty,
+        };
+
+        let mut joined = HashMap::new();
+        for (path, ty) in assigned {
+            let other = self.assigned.remove(&path).unwrap_or(Type::Any);
+            joined.insert(path, ty.join(other));
+        }
+        for (path, _) in self.assigned.drain() {
+            joined.insert(path, Type::Any);
+        }
+        self.assigned = joined;
+
+        ty
+    }
+
+    /// The full path of an access made only of names, like `v.a.b`.
+    fn path(&self, expr: &Expr) -> Option<String> {
+        let Expr::Derived(instruction) = expr else {
+            return None;
+        };
+        let Instruction::Access(accesses) = instruction.as_ref() else {
+            return None;
+        };
+
+        let names = names(self.aliases, accesses);
+        (!names.is_empty() && names.len() == accesses.len()).then(|| names.join("."))
+    }
+
+    fn access(&mut self, accesses: &[AccessExpr]) -> Type {
+        let names = names(self.aliases, accesses);
+        let mut rest = &accesses[names.len()..];
+
+        let mut ty = match rest.first() {
+            Some(AccessExpr::Call(args)) if !names.is_empty() => {
+                let function = names.join(".");
+                rest = &rest[1..];
+                self.call(&function, args)
+            }
+            _ => self.lookup(&names),
+        };
+
+        for access in rest {
+            ty = match access {
+                AccessExpr::Name(name) => self.member(ty, name),
+                AccessExpr::Index(index) => {
+                    self.expr(index);
+                    match ty {
+                        Type::Array(element) => *element,
+                        Type::Any => Type::Any,
+                        found => {
+                            self.errors.push(TypeError::Mismatch {
+                                expected: Type::Array(Box::new(Type::Any)),
+                                found,
+                                expr: Expr::Derived(Box::new(Instruction::Access(
+                                    accesses.to_vec(),
+                                )))
+                                .to_string(),
+                            });
+                            Type::Any
+                        }
+                    }
+                }
+                AccessExpr::Call(args) => {
+                    for arg in args {
+                        self.expr(arg);
+                    }
+                    Type::Any
+                }
+                AccessExpr::Slot(_) => Type::Any,
+            };
+        }
+
+        ty
+    }
+
+    /// The type of the longest declared or assigned prefix of `names`, with
+    /// the members after it looked up in turn.
+    fn lookup(&mut self, names: &[&str]) -> Type {
+        for len in (1..=names.len()).rev() {
+            let path = names[..len].join(".");
+            let found = self
+                .assigned
+                .get(&path)
+                .or_else(|| self.signatures.values.get(&path))
+                .cloned();
+
+            if let Some(ty) = found {
+                return names[len..]
+                    .iter()
+                    .fold(ty, |ty, name| self.member(ty, name));
+            }
+        }
+
+        Type::Any
+    }
+
+    fn member(&mut self, ty: Type, name: &str) -> Type {
+        match ty {
+            Type::Struct | Type::Any => Type::Any,
+            found => {
+                self.errors.push(TypeError::Mismatch {
+                    expected: Type::Struct,
+                    found,
+                    expr: format!(".{name}"),
+                });
+                Type::Any
+            }
+        }
+    }
+
+    fn call(&mut self, function: &str, args: &[Expr]) -> Type {
+        let Some(signature) = self.signatures.functions.get(function) else {
+            for arg in args {
+                self.expr(arg);
+            }
+            return Type::Any;
+        };
+
+        let takes_more = signature.variadic.is_some();
+        if args.len() < signature.params.len()
+            || (args.len() > signature.params.len() && !takes_more)
+        {
+            self.errors.push(TypeError::Arguments {
+                function: function.to_string(),
+                expected: signature.params.len(),
+                found: args.len(),
+            });
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            let expected = signature
+                .params
+                .get(i)
+                .or(signature.variadic.as_ref())
+                .cloned()
+                .unwrap_or(Type::Any);
+            self.expect(expected, arg);
+        }
+
+        signature.returns.clone()
+    }
+}
+
+fn root(path: &str) -> &str {
+    path.split('.').next().unwrap_or(path)
+}
+
+/// The leading names of an access, with the root's alias resolved.
+fn names<'b>(aliases: &'b HashMap<String, String>, accesses: &'b [AccessExpr]) -> Vec<&'b str> {
+    accesses
+        .iter()
+        .map_while(|access| match access {
+            AccessExpr::Name(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .enumerate()
+        .map(|(i, name)| match i {
+            0 => aliases.get(name).map_or(name, String::as_str),
+            _ => name,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Runtime, Value};
+
+    use super::{Signature, Signatures, Type, TypeError};
+
+    fn check(source: &str) -> Vec<TypeError> {
+        let runtime = Runtime::builder()
+            .constant("math.pi", 3.0)
+            .constant("pi", 3.0)
+            .alias("q", "query")
+            .alias("t", "temp")
+            .build();
+
+        let signatures = Signatures::new()
+            .query("query.name", Type::String)
+            .query("query.anim_time", Type::Number)
+            .query("query.bones", Type::Array(Box::new(Type::Struct)))
+            .variable("variable.speed", Type::Number)
+            .function(
+                "math.max",
+                Signature {
+                    params: vec![Type::Number],
+                    variadic: Some(Type::Number),
+                    returns: Type::Number,
+                },
+            )
+            .function(
+                "query.is_item_equipped",
+                Signature {
+                    params: vec![Type::String],
+                    variadic: None,
+                    returns: Type::Number,
+                },
+            );
+
+        runtime.check_types(&runtime.compile(source).unwrap(), &signatures)
+    }
+
+    fn mismatch(expected: Type, found: Type, expr: &str) -> TypeError {
+        TypeError::Mismatch {
+            expected,
+            found,
+            expr: expr.to_string(),
+        }
+    }
+
+    #[test]
+    fn well_typed() {
+        for source in [
+            "q.anim_time * math.pi + 1",
+            "math.max(q.anim_time, 1, 2) ? q.name : \"none\"",
+            "t.x = q.name; t.x == \"a\"",
+            "q.is_item_equipped(\"sword\") ? 1 : 0",
+            "q.bones[0].rotation.x + v.unknown",
+            "variable.speed = q.anim_time * 2",
+            "q.missing ?? 1",
+        ] {
+            assert_eq!(Vec::<TypeError>::new(), check(source), "{source}");
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "q.name")],
+            check("q.name + 1")
+        );
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "t.s")],
+            check("t.s = \"a\"; return t.s * 2;")
+        );
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "q.name")],
+            check("!q.name")
+        );
+        // Reassigning changes the type from then on.
+        assert_eq!(
+            Vec::<TypeError>::new(),
+            check("t.x = \"s\"; t.x = 1; return t.x + 1;")
+        );
+    }
+
+    #[test]
+    fn branches() {
+        // Each branch starts from the types before the condition.
+        assert_eq!(
+            Vec::<TypeError>::new(),
+            check("t.x = 1; q.anim_time ? (t.x = \"s\") : t.x + 1")
+        );
+        // Afterwards a variable may have either branch's type.
+        for source in [
+            "t.x = 1; q.anim_time ? (t.x = \"s\"); return t.x + 1;",
+            "t.x = 1; q.anim_time ? 0 : (t.x = \"s\"); return t.x + 1;",
+            "t.x = 1; q.missing ?? (t.x = \"s\"); return t.x + 1;",
+        ] {
+            assert_eq!(Vec::<TypeError>::new(), check(source), "{source}");
+        }
+        // Unless both leave it the same.
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "t.x")],
+            check("q.anim_time ? (t.x = \"a\") : (t.x = \"b\"); return t.x + 1;")
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "q.name")],
+            check("q.name ? 1 : 2")
+        );
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "1 ? \"a\" : \"b\"")],
+            check("(1 ? \"a\" : \"b\") ? 1 : 2")
+        );
+    }
+
+    #[test]
+    fn calls() {
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "q.name")],
+            check("math.max(1, q.name)")
+        );
+        assert_eq!(
+            vec![
+                TypeError::Arguments {
+                    function: "query.is_item_equipped".to_string(),
+                    expected: 1,
+                    found: 2
+                },
+                mismatch(Type::String, Type::Number, "1")
+            ],
+            check("q.is_item_equipped(1, 2)")
+        );
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::String, "\"fast\"")],
+            check("variable.speed = \"fast\"")
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let constant = |path: &str| TypeError::Constant {
+            path: path.to_string(),
+        };
+
+        assert_eq!(vec![constant("pi")], check("pi = \"a\""));
+        assert_eq!(vec![constant("math.pi")], check("math.pi = 1"));
+        assert_eq!(vec![constant("query.name.x")], check("q.name.x = 1"));
+        // The constant keeps its type.
+        assert_eq!(vec![constant("pi")], check("pi = \"a\"; return pi * 2;"));
+    }
+
+    #[test]
+    fn accesses() {
+        assert_eq!(
+            vec![mismatch(Type::Struct, Type::Number, ".x")],
+            check("q.anim_time.x")
+        );
+        assert_eq!(
+            vec![mismatch(
+                Type::Array(Box::new(Type::Any)),
+                Type::String,
+                "q.name[0]"
+            )],
+            check("q.name[0]")
+        );
+        assert_eq!(Type::Struct, Type::of(&Value::Struct(Default::default())));
+    }
+
+    #[test]
+    fn folded_null() {
+        let runtime = Runtime::builder()
+            .constant("nothing", Value::Null)
+            .optimise(true)
+            .build();
+
+        assert_eq!(
+            vec![mismatch(Type::Number, Type::Null, "null")],
+            runtime.check_types(&runtime.compile("nothing + 1").unwrap(), &Signatures::new())
+        );
+    }
+}
@@ -1,17 +1,27 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+use std::{collections::HashMap, fmt::Debug};
 
-use crate::interpreter::MolangError;
+use crate::{
+    interpreter::MolangError,
+    sync::{lock, MaybeSend, Pointer, Shared},
+};
 
 pub trait MolangEq {
     fn molang_eq(&self, rhs: &Value) -> bool;
 }
 
+/// The type of Molang numbers: `f32` as in Bedrock, or `f64` with the `f64` feature.
+#[cfg(not(feature = "f64"))]
+pub type Number = f32;
+#[cfg(feature = "f64")]
+pub type Number = f64;
+
 #[derive(Debug, Clone)]
 pub enum Value {
-    Number(f32),
-    String(String),
+    Number(Number),
+    /// Shared, so strings from literals are not copied each time they are read.
+    String(Pointer<str>),
     Struct(HashMap<String, Value>),
-    External(Rc<RefCell<dyn External>>),
+    External(Shared<dyn External>),
     Function(Function),
     Null,
 }
@@ -51,7 +61,9 @@ impl MolangEq for Value {
 
             Value::External(e) => {
                 if let Value::External(rhs) = rhs {
-                    e.borrow().molang_eq(&Value::External(rhs.clone()))
+                    // The same external is always equal to itself, and checking
+                    // here saves locking it twice.
+                    Pointer::ptr_eq(e, rhs) || lock(e).molang_eq(&Value::External(rhs.clone()))
                 } else {
                     false
                 }
@@ -63,19 +75,20 @@ impl MolangEq for Value {
                     false
                 }
             }
-            Value::Null => {
-                if let Value::Null = rhs {
-                    true
-                } else {
-                    false
-                }
-            }
+            Value::Null => matches!(rhs, Value::Null),
         }
     }
 }
 
-pub trait External: Debug + MolangEq {
+pub trait External: Debug + MolangEq + MaybeSend {
     fn get(&mut self, property: &str) -> Value;
+
+    /// Reads `property` for a script, failing where `get` could only give
+    /// null. Defaults to `get`.
+    fn try_get(&mut self, property: &str) -> Result<Value, MolangError> {
+        Ok(self.get(property))
+    }
+
     fn set(&mut self, property: &str, value: Value) -> Result<(), MolangError>;
     fn call_function(&mut self, function: &str, args: Vec<Value>) -> Result<Value, MolangError>;
 
@@ -83,9 +96,14 @@ pub trait External: Debug + MolangEq {
     fn index_set(&mut self, index: Value, value: Value) -> Result<(), MolangError>;
 }
 
+#[cfg(not(feature = "sync"))]
+pub type NativeFunction = dyn FnMut(Vec<Value>) -> Result<Value, MolangError>;
+#[cfg(feature = "sync")]
+pub type NativeFunction = dyn FnMut(Vec<Value>) -> Result<Value, MolangError> + Send;
+
 #[derive(Clone)]
 pub struct Function {
-    pub f: Rc<RefCell<dyn FnMut(Vec<Value>) -> Result<Value, MolangError>>>,
+    pub f: Shared<NativeFunction>,
 }
 
 impl Debug for Function {
@@ -140,24 +158,32 @@ where
     }
 }
 
-impl ToMolangValue for f32 {
-    fn to_value(self) -> Value {
-        Value::Number(self)
-    }
-}
+macro_rules! impl_number {
+    ($($t:ty),*) => {$(
+        #[allow(clippy::unnecessary_cast)]
+        impl ToMolangValue for $t {
+            fn to_value(self) -> Value {
+                Value::Number(self as Number)
+            }
+        }
 
-impl FromMolangValue for f32 {
-    fn from_value(v: Value) -> Result<Self, MolangError> {
-        match v {
-            Value::Number(n) => Ok(n),
-            a => Err(MolangError::TypeError(
-                "Number".to_string(),
-                format!("{a:?}"),
-            )),
+        #[allow(clippy::unnecessary_cast)]
+        impl FromMolangValue for $t {
+            fn from_value(v: Value) -> Result<Self, MolangError> {
+                match v {
+                    Value::Number(n) => Ok(n as $t),
+                    a => Err(MolangError::TypeError(
+                        "Number".to_string(),
+                        format!("{a:?}"),
+                    )),
+                }
+            }
         }
-    }
+    )*};
 }
 
+impl_number!(f32, f64);
+
 impl ToMolangValue for HashMap<String, Value> {
     fn to_value(self) -> Value {
         Value::Struct(self)
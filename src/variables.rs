@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use crate::{sync::lock, MolangError, Value};
+
+/// Storage for the variables a script reads and assigns, so hosts can keep them
+/// wherever they already live instead of copying into a `HashMap`.
+///
+/// Only `get` and `set` are required; the path methods default to copying the
+/// root value out, walking it and writing it back, and can be overridden when
+/// the store can address members directly.
+pub trait VariableStore {
+    fn get(&self, name: &str) -> Option<Value>;
+
+    /// Overwrites an existing variable.
+    fn set(&mut self, name: &str, value: Value) -> Result<(), MolangError>;
+
+    /// Reads `name.path[0].path[1]...`.
+    fn get_path(&self, name: &str, path: &[&str]) -> Result<Value, MolangError> {
+        let mut current = self
+            .get(name)
+            .ok_or_else(|| MolangError::VariableNotFound(name.to_string()))?;
+
+        for member in path {
+            current = read_member(current, member)?;
+        }
+
+        Ok(current)
+    }
+
+    fn set_path(
+        &mut self,
+        name: &str,
+        path: &[PathSegment],
+        value: Value,
+    ) -> Result<(), MolangError> {
+        if path.is_empty() {
+            return self.set(name, value);
+        }
+
+        let mut root = self
+            .get(name)
+            .ok_or_else(|| MolangError::VariableNotFound(name.to_string()))?;
+
+        assign(&mut root, path, value)?;
+
+        self.set(name, root)
+    }
+
+    /// How many members the struct at `name.path[0]...` has, or `None` if it
+    /// is not a struct, for checking [`crate::Limits::max_struct_size`].
+    fn struct_len(&self, name: &str, path: &[&str]) -> Option<usize> {
+        match self.get_path(name, path) {
+            Ok(Value::Struct(struc)) => Some(struc.len()),
+            _ => None,
+        }
+    }
+
+    /// Whether `name.path[0]...` already exists, so that assigning it adds no
+    /// member to the struct holding it.
+    fn has_member(&self, name: &str, path: &[&str]) -> bool {
+        self.get(name)
+            .is_some_and(|root| member(&root, path).is_some())
+    }
+}
+
+impl VariableStore for HashMap<String, Value> {
+    fn get(&self, name: &str) -> Option<Value> {
+        HashMap::get(self, name).cloned()
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), MolangError> {
+        match self.get_mut(name) {
+            Some(target) => {
+                *target = value;
+                Ok(())
+            }
+            None => Err(MolangError::VariableNotFound(name.to_string())),
+        }
+    }
+
+    fn get_path(&self, name: &str, path: &[&str]) -> Result<Value, MolangError> {
+        let mut current = HashMap::get(self, name)
+            .ok_or_else(|| MolangError::VariableNotFound(name.to_string()))?;
+
+        for (i, member) in path.iter().enumerate() {
+            match current {
+                Value::Struct(struc) => current = struc.get(*member).unwrap_or(&Value::Null),
+                _ => {
+                    let mut current = current.clone();
+                    for member in &path[i..] {
+                        current = read_member(current, member)?;
+                    }
+                    return Ok(current);
+                }
+            }
+        }
+
+        Ok(current.clone())
+    }
+
+    fn set_path(
+        &mut self,
+        name: &str,
+        path: &[PathSegment],
+        value: Value,
+    ) -> Result<(), MolangError> {
+        let target = self
+            .get_mut(name)
+            .ok_or_else(|| MolangError::VariableNotFound(name.to_string()))?;
+
+        assign(target, path, value)
+    }
+
+    fn struct_len(&self, name: &str, path: &[&str]) -> Option<usize> {
+        struct_len(HashMap::get(self, name)?, path)
+    }
+
+    fn has_member(&self, name: &str, path: &[&str]) -> bool {
+        HashMap::get(self, name).is_some_and(|root| member(root, path).is_some())
+    }
+}
+
+/// How many members the struct at `path` within `value` has.
+pub(crate) fn struct_len(value: &Value, path: &[&str]) -> Option<usize> {
+    match member(value, path)? {
+        Value::Struct(struc) => Some(struc.len()),
+        _ => None,
+    }
+}
+
+/// The struct member at `path` within `value`, if it exists.
+pub(crate) fn member<'a>(mut value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    for member in path {
+        match value {
+            Value::Struct(struc) => value = struc.get(*member)?,
+            _ => return None,
+        }
+    }
+
+    Some(value)
+}
+
+fn read_member(value: Value, member: &str) -> Result<Value, MolangError> {
+    match value {
+        Value::Struct(mut struc) => Ok(struc.remove(member).unwrap_or(Value::Null)),
+        Value::External(e) => lock(&e).try_get(member),
+        value => Err(MolangError::BadAccess(
+            ".".to_string(),
+            format!("{value:?}"),
+        )),
+    }
+}
+
+/// One step of an assignment target after its root name, e.g. `.x` or `[0]`.
+#[derive(Debug)]
+pub enum PathSegment<'a> {
+    Name(&'a str),
+    Index(Value),
+}
+
+/// Writes `value` into `target` at `path`, creating missing struct members and
+/// routing writes into externals through their setters.
+pub fn assign(target: &mut Value, path: &[PathSegment], value: Value) -> Result<(), MolangError> {
+    let Some((segment, rest)) = path.split_first() else {
+        *target = value;
+        return Ok(());
+    };
+
+    match (target, segment) {
+        (Value::Struct(struc), PathSegment::Name(name)) => assign(
+            struc
+                .entry(name.to_string())
+                .or_insert_with(|| Value::Struct(HashMap::new())),
+            rest,
+            value,
+        ),
+        (Value::External(e), PathSegment::Name(name)) => {
+            if rest.is_empty() {
+                return lock(e).set(name, value);
+            }
+
+            let mut inner = lock(e).try_get(name)?;
+            assign(&mut inner, rest, value)?;
+
+            // Externals are shared handles, so writes into them have already landed.
+            if matches!(inner, Value::External(_)) {
+                Ok(())
+            } else {
+                lock(e).set(name, inner)
+            }
+        }
+        (Value::External(e), PathSegment::Index(index)) => {
+            if rest.is_empty() {
+                return lock(e).index_set(index.clone(), value);
+            }
+
+            let mut inner = lock(e).index_get(index.clone())?;
+            assign(&mut inner, rest, value)?;
+
+            if matches!(inner, Value::External(_)) {
+                Ok(())
+            } else {
+                lock(e).index_set(index.clone(), inner)
+            }
+        }
+        (target, PathSegment::Name(_)) => Err(MolangError::BadAccess(
+            ".".to_string(),
+            format!("{target:?}"),
+        )),
+        (target, PathSegment::Index(_)) => Err(MolangError::BadAccess(
+            "[]".to_string(),
+            format!("{target:?}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{compile, run, MolangError, Number, Value};
+
+    use super::VariableStore;
+
+    /// Keeps `variable.*` flat, the way an ECS component would.
+    #[derive(Default)]
+    struct Flat {
+        fields: Vec<(String, Number)>,
+    }
+
+    impl VariableStore for Flat {
+        fn get(&self, name: &str) -> Option<Value> {
+            (name == "variable").then(|| {
+                Value::Struct(
+                    self.fields
+                        .iter()
+                        .map(|(k, v)| (k.clone(), Value::Number(*v)))
+                        .collect(),
+                )
+            })
+        }
+
+        fn set(&mut self, name: &str, value: Value) -> Result<(), MolangError> {
+            match (name, value) {
+                ("variable", Value::Struct(struc)) => {
+                    self.fields.clear();
+                    for (k, v) in struc {
+                        match v {
+                            Value::Number(n) => self.fields.push((k, n)),
+                            v => {
+                                return Err(MolangError::TypeError(
+                                    "Number".to_string(),
+                                    format!("{v:?}"),
+                                ))
+                            }
+                        }
+                    }
+                    Ok(())
+                }
+                (name, _) => Err(MolangError::NotAssignable(name.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_store() {
+        let mut store = Flat::default();
+        let mut aliases = HashMap::new();
+        aliases.insert("v".to_string(), "variable".to_string());
+
+        run(
+            &compile("v.speed = 2; v.offset = v.speed * 3;").unwrap(),
+            &HashMap::new(),
+            &mut store,
+            &aliases,
+        )
+        .unwrap();
+
+        assert!(store.fields.contains(&("offset".to_string(), 6.0)));
+        assert!(matches!(
+            run(
+                &compile("other = 1").unwrap(),
+                &HashMap::new(),
+                &mut store,
+                &aliases
+            ),
+            Err(MolangError::NotAssignable(_))
+        ));
+    }
+
+    #[test]
+    fn hash_map_paths() {
+        let mut variables: HashMap<String, Value> = HashMap::new();
+        variables.insert("a".to_string(), Value::Struct(HashMap::new()));
+
+        assert!(matches!(
+            variables.set("missing", Value::Null),
+            Err(MolangError::VariableNotFound(_))
+        ));
+
+        variables
+            .set_path(
+                "a",
+                &[super::PathSegment::Name("b"), super::PathSegment::Name("c")],
+                Value::Number(1.0),
+            )
+            .unwrap();
+
+        assert_eq!(
+            Value::Number(1.0),
+            variables.get_path("a", &["b", "c"]).unwrap()
+        );
+        assert_eq!(Value::Null, variables.get_path("a", &["x"]).unwrap());
+        assert!(matches!(
+            variables.get_path("a", &["b", "c", "d"]),
+            Err(MolangError::BadAccess(_, _))
+        ));
+    }
+}
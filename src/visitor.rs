@@ -0,0 +1,284 @@
+use crate::{
+    blockiser::Block,
+    parser::{AccessExpr, Instruction},
+    Expr, Symbol, Value,
+};
+
+/// Walks a compiled block without changing it. Each method defaults to its
+/// `walk_` function, which visits the children, so an implementation only
+/// overrides the nodes it cares about and calls the `walk_` function to keep
+/// descending.
+pub trait Visitor {
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_literal(&mut self, _value: &Value) {}
+
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        walk_instruction(self, instruction)
+    }
+
+    /// A whole chain such as `q.f(1)[0]`, before its parts.
+    fn visit_access(&mut self, accesses: &[AccessExpr]) {
+        walk_access(self, accesses)
+    }
+
+    fn visit_access_expr(&mut self, access: &AccessExpr) {
+        walk_access_expr(self, access)
+    }
+
+    fn visit_name(&mut self, _name: &Symbol) {}
+
+    fn visit_slot(&mut self, _slot: usize) {}
+
+    fn visit_call(&mut self, args: &[Expr]) {
+        walk_call(self, args)
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for statement in &block.statements {
+        visitor.visit_expr(statement);
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(value) => visitor.visit_literal(value),
+        Expr::Derived(instruction) => visitor.visit_instruction(instruction),
+    }
+}
+
+pub fn walk_instruction<V: Visitor + ?Sized>(visitor: &mut V, instruction: &Instruction) {
+    match instruction {
+        Instruction::Add(left, right)
+        | Instruction::Subtract(left, right)
+        | Instruction::Multiply(left, right)
+        | Instruction::Divide(left, right)
+        | Instruction::Conditional(left, right)
+        | Instruction::Colon(left, right)
+        | Instruction::NullishCoalescing(left, right)
+        | Instruction::Equality(left, right)
+        | Instruction::Assignment(left, right) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Instruction::Not(expr) | Instruction::Return(expr) => visitor.visit_expr(expr),
+        Instruction::Access(accesses) => visitor.visit_access(accesses),
+    }
+}
+
+pub fn walk_access<V: Visitor + ?Sized>(visitor: &mut V, accesses: &[AccessExpr]) {
+    for access in accesses {
+        visitor.visit_access_expr(access);
+    }
+}
+
+pub fn walk_access_expr<V: Visitor + ?Sized>(visitor: &mut V, access: &AccessExpr) {
+    match access {
+        AccessExpr::Name(name) => visitor.visit_name(name),
+        AccessExpr::Slot(slot) => visitor.visit_slot(*slot),
+        AccessExpr::Index(index) => visitor.visit_expr(index),
+        AccessExpr::Call(args) => visitor.visit_call(args),
+    }
+}
+
+pub fn walk_call<V: Visitor + ?Sized>(visitor: &mut V, args: &[Expr]) {
+    for arg in args {
+        visitor.visit_expr(arg);
+    }
+}
+
+/// Walks a compiled block in place, as [`Visitor`] does, for passes which
+/// rewrite it.
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_literal_mut(&mut self, _value: &mut Value) {}
+
+    fn visit_instruction_mut(&mut self, instruction: &mut Instruction) {
+        walk_instruction_mut(self, instruction)
+    }
+
+    fn visit_access_mut(&mut self, accesses: &mut Vec<AccessExpr>) {
+        walk_access_mut(self, accesses)
+    }
+
+    fn visit_access_expr_mut(&mut self, access: &mut AccessExpr) {
+        walk_access_expr_mut(self, access)
+    }
+
+    fn visit_name_mut(&mut self, _name: &mut Symbol) {}
+
+    fn visit_slot_mut(&mut self, _slot: &mut usize) {}
+
+    fn visit_call_mut(&mut self, args: &mut Vec<Expr>) {
+        walk_call_mut(self, args)
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for statement in &mut block.statements {
+        visitor.visit_expr_mut(statement);
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Literal(value) => visitor.visit_literal_mut(value),
+        Expr::Derived(instruction) => visitor.visit_instruction_mut(instruction),
+    }
+}
+
+pub fn walk_instruction_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    instruction: &mut Instruction,
+) {
+    match instruction {
+        Instruction::Add(left, right)
+        | Instruction::Subtract(left, right)
+        | Instruction::Multiply(left, right)
+        | Instruction::Divide(left, right)
+        | Instruction::Conditional(left, right)
+        | Instruction::Colon(left, right)
+        | Instruction::NullishCoalescing(left, right)
+        | Instruction::Equality(left, right)
+        | Instruction::Assignment(left, right) => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        Instruction::Not(expr) | Instruction::Return(expr) => visitor.visit_expr_mut(expr),
+        Instruction::Access(accesses) => visitor.visit_access_mut(accesses),
+    }
+}
+
+pub fn walk_access_mut<V: VisitorMut + ?Sized>(visitor: &mut V, accesses: &mut Vec<AccessExpr>) {
+    for access in accesses {
+        visitor.visit_access_expr_mut(access);
+    }
+}
+
+pub fn walk_access_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, access: &mut AccessExpr) {
+    match access {
+        AccessExpr::Name(name) => visitor.visit_name_mut(name),
+        AccessExpr::Slot(slot) => visitor.visit_slot_mut(slot),
+        AccessExpr::Index(index) => visitor.visit_expr_mut(index),
+        AccessExpr::Call(args) => visitor.visit_call_mut(args),
+    }
+}
+
+pub fn walk_call_mut<V: VisitorMut + ?Sized>(visitor: &mut V, args: &mut Vec<Expr>) {
+    for arg in args {
+        visitor.visit_expr_mut(arg);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::{compile, parser::Instruction, Expr, Number, Symbol, Value};
+
+    use super::{walk_expr_mut, walk_instruction, Visitor, VisitorMut};
+
+    /// Folds arithmetic on number literals, bottom up.
+    pub(crate) struct Fold;
+
+    impl VisitorMut for Fold {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            walk_expr_mut(self, expr);
+
+            let Expr::Derived(instruction) = expr else {
+                return;
+            };
+            let (Instruction::Add(l, r)
+            | Instruction::Subtract(l, r)
+            | Instruction::Multiply(l, r)
+            | Instruction::Divide(l, r)) = instruction.as_ref()
+            else {
+                return;
+            };
+            let (Expr::Literal(Value::Number(l)), Expr::Literal(Value::Number(r))) = (l, r) else {
+                return;
+            };
+
+            let n: Number = match instruction.as_ref() {
+                Instruction::Add(_, _) => l + r,
+                Instruction::Subtract(_, _) => l - r,
+                Instruction::Multiply(_, _) => l * r,
+                _ => l / r,
+            };
+            *expr = Expr::Literal(Value::Number(n));
+        }
+    }
+
+    /// Counts the arithmetic left in a block.
+    #[derive(Default)]
+    pub(crate) struct Arithmetic {
+        pub(crate) count: usize,
+    }
+
+    impl Visitor for Arithmetic {
+        fn visit_instruction(&mut self, instruction: &Instruction) {
+            if let Instruction::Add(_, _)
+            | Instruction::Subtract(_, _)
+            | Instruction::Multiply(_, _)
+            | Instruction::Divide(_, _) = instruction
+            {
+                self.count += 1;
+            }
+            walk_instruction(self, instruction);
+        }
+    }
+
+    /// Every name and the arity of every call, in source order.
+    #[derive(Default)]
+    struct Names {
+        names: Vec<String>,
+        calls: Vec<usize>,
+        assignments: usize,
+    }
+
+    impl Visitor for Names {
+        fn visit_instruction(&mut self, instruction: &Instruction) {
+            if let Instruction::Assignment(_, _) = instruction {
+                self.assignments += 1;
+            }
+            walk_instruction(self, instruction);
+        }
+
+        fn visit_name(&mut self, name: &Symbol) {
+            self.names.push(name.to_string());
+        }
+
+        fn visit_call(&mut self, args: &[Expr]) {
+            self.calls.push(args.len());
+            super::walk_call(self, args);
+        }
+    }
+
+    #[test]
+    fn names() {
+        let mut names = Names::default();
+        names.visit_block(
+            &compile("lolz.nested.property = math.max(q.a[t.i], 2, f()); return lolz.x ?? 1;")
+                .unwrap(),
+        );
+
+        assert_eq!(
+            vec!["lolz", "nested", "property", "math", "max", "q", "a", "t", "i", "f", "lolz", "x"],
+            names.names
+        );
+        assert_eq!(vec![3, 0], names.calls);
+        assert_eq!(1, names.assignments);
+    }
+}